@@ -3,7 +3,10 @@
 //! ## 計測方法
 //! a?^n a^nという正規表現を、a^nという文字列にマッチングさせる
 //! ただし、a?^nとa^nは、a?とaのn回の繰り返し
-//! 計測は幅優先探索と深さ優先探索で行う
+//! 計測は幅優先探索と深さ優先探索、遅延DFAで行う
+//!
+//! また、ログ形式の行の集合に対して、同じ正規表現で繰り返しマッチングを行い、
+//! コンパイル済みの正規表現を使い回した場合の各評価器の速度も計測する
 //!
 //! ## n = 3 の場合の例
 //!
@@ -22,7 +25,7 @@
 //! 実行後は、target/criterion/reports/index.htmlというファイルが生成されるため、
 //! それをWebブラウザで閲覧する
 use criterion::{criterion_group, criterion_main, Criterion};
-use regex_engine::engine::do_matching;
+use regex_engine::engine::{do_matching, RegexBuilder};
 use std::time::Duration;
 
 /// (計測のid, a?^n a^nという正規表現、文字列)というタプル
@@ -81,5 +84,91 @@ fn width_first(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, width_first, depth_first);
+fn lazy_dfa(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Lazy DFA");
+    g.measurement_time(Duration::from_secs(12));
+
+    for i in INPUTS {
+        // do_matchingと同様に、先頭からのマッチングとするため^を付与。
+        // 探索のみを計測するため、コンパイルは計測外で1度だけ行う
+        let expr = format!("^{}", i.1);
+        let regex = RegexBuilder::new(&expr).lazy_dfa(true).build().unwrap();
+        g.bench_with_input(i.0, &(&regex, i.2), |b, (regex, line)| {
+            b.iter(|| regex.is_match(line).unwrap())
+        });
+    }
+}
+
+/// コンパイルと、状態のキャッシュが空の遅延DFAでの初回の探索を合わせて計測
+fn lazy_dfa_cold(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Lazy DFA (cold)");
+    g.measurement_time(Duration::from_secs(12));
+
+    for i in INPUTS {
+        let expr = format!("^{}", i.1);
+        g.bench_with_input(i.0, &(expr.as_str(), i.2), |b, args| {
+            b.iter(|| {
+                let regex = RegexBuilder::new(args.0).lazy_dfa(true).build().unwrap();
+                regex.is_match(args.1).unwrap()
+            })
+        });
+    }
+}
+
+/// ログ形式の行をn行生成
+fn log_lines(n: usize) -> Vec<String> {
+    let levels = ["INFO", "WARN", "ERROR", "DEBUG"];
+    (0..n)
+        .map(|i| {
+            format!(
+                "2024-01-{:02} 12:{:02}:{:02} [{}] request id={} path=/api/v1/items/{} status={}",
+                i % 28 + 1,
+                i % 60,
+                (i * 7) % 60,
+                levels[i % levels.len()],
+                i * 31,
+                i % 1000,
+                if i % 17 == 0 { 500 } else { 200 },
+            )
+        })
+        .collect()
+}
+
+fn log_scan(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Log scan");
+    g.measurement_time(Duration::from_secs(12));
+
+    let lines = log_lines(1000);
+    let expr = r"\[(ERROR|WARN)\].*status=5[0-9][0-9]";
+    let backends = [
+        ("depth first", true, false),
+        ("width first", false, false),
+        ("lazy DFA", false, true),
+    ];
+
+    for (id, is_depth, is_dfa) in backends {
+        let regex = RegexBuilder::new(expr)
+            .depth_first(is_depth)
+            .lazy_dfa(is_dfa)
+            .build()
+            .unwrap();
+        g.bench_function(id, |b| {
+            b.iter(|| {
+                lines
+                    .iter()
+                    .filter(|line| regex.is_match(line).unwrap())
+                    .count()
+            })
+        });
+    }
+}
+
+criterion_group!(
+    benches,
+    width_first,
+    depth_first,
+    lazy_dfa,
+    lazy_dfa_cold,
+    log_scan
+);
 criterion_main!(benches);
@@ -0,0 +1,83 @@
+#!/usr/bin/env python3
+"""src/engine/unicode_tables.rs を生成する
+
+一般カテゴリ（L, N）は Python の unicodedata から求める。
+unicodedata は用字（Script）を持たないため、Hiragana, Katakana, Han は
+Scripts.txt の該当する範囲をここに転記している。
+
+    python3 scripts/gen_unicode_tables.py > src/engine/unicode_tables.rs
+"""
+import sys
+import unicodedata
+
+# Scripts.txt より転記。unicodedata.unidata_version と同じ版のものを用いる
+SCRIPTS = {
+    "Hiragana": [
+        (0x3041, 0x3096), (0x309D, 0x309F), (0x1B001, 0x1B11F),
+        (0x1B150, 0x1B152), (0x1F200, 0x1F200),
+    ],
+    "Katakana": [
+        (0x30A1, 0x30FA), (0x30FD, 0x30FF), (0x31F0, 0x31FF), (0x32D0, 0x32FE),
+        (0x3300, 0x3357), (0xFF66, 0xFF6F), (0xFF71, 0xFF9D), (0x1AFF0, 0x1AFF3),
+        (0x1AFF5, 0x1AFFB), (0x1AFFD, 0x1AFFE), (0x1B000, 0x1B000),
+        (0x1B120, 0x1B122), (0x1B164, 0x1B167),
+    ],
+    "Han": [
+        (0x2E80, 0x2E99), (0x2E9B, 0x2EF3), (0x2F00, 0x2FD5), (0x3005, 0x3005),
+        (0x3007, 0x3007), (0x3021, 0x3029), (0x3038, 0x303B), (0x3400, 0x4DBF),
+        (0x4E00, 0x9FFF), (0xF900, 0xFA6D), (0xFA70, 0xFAD9), (0x16FE2, 0x16FE3),
+        (0x16FF0, 0x16FF1), (0x20000, 0x2A6DF), (0x2A700, 0x2B738),
+        (0x2B740, 0x2B81D), (0x2B820, 0x2CEA1), (0x2CEB0, 0x2EBE0),
+        (0x2F800, 0x2FA1D), (0x30000, 0x3134A),
+    ],
+}
+
+# 一般カテゴリの名前と、それに含まれるカテゴリ
+CATEGORIES = {
+    "L": ["Lu", "Ll", "Lt", "Lm", "Lo"],
+    "N": ["Nd", "Nl", "No"],
+}
+
+
+def category_ranges(cats):
+    ranges = []
+    for cp in range(0x110000):
+        if unicodedata.category(chr(cp)) not in cats:
+            continue
+        if ranges and ranges[-1][1] == cp - 1:
+            ranges[-1] = (ranges[-1][0], cp)
+        else:
+            ranges.append((cp, cp))
+    return ranges
+
+
+def write_table(out, name, ranges):
+    out.write(f"const {name.upper()}: &[(char, char)] = &[\n")
+    for start, end in ranges:
+        out.write(f"    ('\\u{{{start:x}}}', '\\u{{{end:x}}}'),\n")
+    out.write("];\n\n")
+
+
+def main():
+    out = sys.stdout
+    version = unicodedata.unidata_version
+    out.write(f"//! Unicodeの用字と一般カテゴリの表（Unicode {version}）\n")
+    out.write("//!\n")
+    out.write("//! scripts/gen_unicode_tables.pyで生成したもの。直接編集しないこと\n\n")
+
+    tables = dict(SCRIPTS)
+    for name, cats in CATEGORIES.items():
+        tables[name] = category_ranges(cats)
+
+    out.write("/// \\p{...}で指定できるプロパティ名と、その文字の範囲（昇順）\n")
+    out.write("pub const PROPERTIES: &[(&str, &[(char, char)])] = &[\n")
+    for name in tables:
+        out.write(f'    ("{name}", {name.upper()}),\n')
+    out.write("];\n\n")
+
+    for name, ranges in tables.items():
+        write_table(out, name, ranges)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,564 @@
+//! grepのようなコマンドラインインターフェース
+use regex_engine::{
+    engine::{Regex, RegexBuilder},
+    helper::DynError,
+};
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+};
+
+pub const USAGE: &str = "usage: regex_engine [OPTIONS] PATTERN [FILE...]
+
+FILEを指定しない場合、またはFILEが-の場合は標準入力から読み込む
+
+options:
+  -i, --ignore-case         大文字と小文字を区別しない
+  -v, --invert-match        マッチしない行を表示
+  -c, --count               マッチした行数のみを表示
+  -n, --line-number         行番号を表示
+  -o, --only-matching       マッチした部分のみを表示
+  -l, --files-with-matches  マッチした行を含むファイル名のみを表示
+  -r, --recursive           ディレクトリを再帰的に探索
+  -A, --after-context NUM   マッチした行の後ろNUM行を表示
+  -B, --before-context NUM  マッチした行の前NUM行を表示
+  -C, --context NUM         マッチした行の前後NUM行を表示
+      --color[=WHEN]        マッチした部分を色付けする。WHENはauto, always, never
+      --debug[=FORMAT]      正規表現のASTと命令列を表示。FORMATはtext, dot, json
+                            dotとjsonの場合は、表示のみを行い探索しない
+      --generate NUM        PATTERNの全体にマッチする文字列をNUM個生成して表示し、探索しない
+      --near-miss           --generateで、1文字だけ異なりマッチしない文字列を生成
+      --seed NUM            --generateで用いる乱数のシード。省略時は現在時刻から決める
+  -h, --help                このヘルプを表示";
+
+/// 標準入力を表すファイル名
+const STDIN_NAME: &str = "(standard input)";
+
+// 色付けに用いるエスケープシーケンス
+const COLOR_MATCH: &str = "\x1b[01;31m";
+const COLOR_FILE: &str = "\x1b[35m";
+const COLOR_LINE: &str = "\x1b[32m";
+const COLOR_SEP: &str = "\x1b[36m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+/// 色付けを行うか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+/// --debugで表示する形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugFormat {
+    Text, // 命令列の一覧
+    Dot,  // Graphviz
+    Json,
+}
+
+/// コマンドラインオプション
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub pattern: String,
+    pub files: Vec<String>,
+    pub ignore_case: bool,
+    pub invert: bool,
+    pub count: bool,
+    pub line_number: bool,
+    pub only_matching: bool,
+    pub files_with_matches: bool,
+    pub recursive: bool,
+    pub after: usize,
+    pub before: usize,
+    pub color: Color,
+    pub debug: Option<DebugFormat>,
+    pub generate: Option<usize>, // 生成する文字列の数
+    pub near_miss: bool,
+    pub seed: Option<u64>,
+    pub help: bool,
+}
+
+impl Options {
+    /// コマンドライン引数（プログラム名は除く）をパース
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, DynError> {
+        let mut opts = Options {
+            pattern: String::new(),
+            files: Vec::new(),
+            ignore_case: false,
+            invert: false,
+            count: false,
+            line_number: false,
+            only_matching: false,
+            files_with_matches: false,
+            recursive: false,
+            after: 0,
+            before: 0,
+            color: Color::Never,
+            debug: None,
+            generate: None,
+            near_miss: false,
+            seed: None,
+            help: false,
+        };
+
+        // -A, -Bは、順序によらず-Cより優先する
+        let (mut after, mut before, mut context) = (None, None, None);
+        let mut positional = Vec::new();
+        let mut only_positional = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if only_positional || arg == "-" || !arg.starts_with('-') {
+                positional.push(arg);
+                continue;
+            }
+
+            if arg == "--" {
+                only_positional = true;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+
+                match name {
+                    "after-context" | "before-context" | "context" => {
+                        let value = value.or_else(|| args.next());
+                        let n = parse_num(&arg, value)?;
+                        match name {
+                            "after-context" => after = Some(n),
+                            "before-context" => before = Some(n),
+                            _ => context = Some(n),
+                        }
+                        continue;
+                    }
+                    "generate" | "seed" => {
+                        let value = value.or_else(|| args.next());
+                        let n = parse_num(&arg, value)?;
+                        match name {
+                            "generate" => opts.generate = Some(n),
+                            _ => opts.seed = Some(n as u64),
+                        }
+                        continue;
+                    }
+                    "color" | "colour" => {
+                        opts.color = match value.as_deref() {
+                            None | Some("auto") => Color::Auto,
+                            Some("always") => Color::Always,
+                            Some("never") => Color::Never,
+                            Some(v) => return Err(format!("invalid color: {v}").into()),
+                        };
+                        continue;
+                    }
+                    "debug" => {
+                        opts.debug = match value.as_deref() {
+                            None | Some("text") => Some(DebugFormat::Text),
+                            Some("dot") => Some(DebugFormat::Dot),
+                            Some("json") => Some(DebugFormat::Json),
+                            Some(v) => return Err(format!("invalid debug format: {v}").into()),
+                        };
+                        continue;
+                    }
+                    _ => (),
+                }
+
+                if value.is_some() {
+                    return Err(format!("option does not take a value: --{name}").into());
+                }
+                match name {
+                    "ignore-case" => opts.ignore_case = true,
+                    "invert-match" => opts.invert = true,
+                    "count" => opts.count = true,
+                    "line-number" => opts.line_number = true,
+                    "only-matching" => opts.only_matching = true,
+                    "files-with-matches" => opts.files_with_matches = true,
+                    "recursive" => opts.recursive = true,
+                    "near-miss" => opts.near_miss = true,
+                    "help" => opts.help = true,
+                    _ => return Err(format!("unknown option: {arg}").into()),
+                }
+            } else {
+                // -inのように、複数のオプションをまとめて指定できる
+                for (i, c) in arg.char_indices().skip(1) {
+                    match c {
+                        'i' => opts.ignore_case = true,
+                        'v' => opts.invert = true,
+                        'c' => opts.count = true,
+                        'n' => opts.line_number = true,
+                        'o' => opts.only_matching = true,
+                        'l' => opts.files_with_matches = true,
+                        'r' => opts.recursive = true,
+                        'h' => opts.help = true,
+                        'A' | 'B' | 'C' => {
+                            // -A3と-A 3の両方を受け付ける
+                            let rest = &arg[i + 1..];
+                            let value = if rest.is_empty() {
+                                args.next()
+                            } else {
+                                Some(rest.to_string())
+                            };
+                            let n = parse_num(&format!("-{c}"), value)?;
+                            match c {
+                                'A' => after = Some(n),
+                                'B' => before = Some(n),
+                                _ => context = Some(n),
+                            }
+                            break;
+                        }
+                        _ => return Err(format!("unknown option: -{c}").into()),
+                    }
+                }
+            }
+        }
+
+        if opts.help {
+            return Ok(opts);
+        }
+
+        let mut positional = positional.into_iter();
+        opts.pattern = positional.next().ok_or("no pattern")?;
+        opts.files = positional.collect();
+        opts.after = after.or(context).unwrap_or(0);
+        opts.before = before.or(context).unwrap_or(0);
+
+        Ok(opts)
+    }
+
+    /// オプションに従って正規表現をコンパイル
+    ///
+    /// 各行の判定には遅延DFAを用いる。
+    /// 遅延DFAで評価できない場合やマッチした範囲を求める場合は、幅優先探索を用いる。
+    /// 幅優先探索では後方参照を評価できないため、後方参照を含む場合のみ深さ優先探索を用いる
+    pub fn build_regex(&self) -> Result<Regex, DynError> {
+        let mut builder = RegexBuilder::new(&self.pattern);
+        builder.case_insensitive(self.ignore_case).lazy_dfa(true);
+        // 深さ優先探索ならコンパイルできる場合は、後方参照を含む。
+        // どちらでもコンパイルできない場合は、パースエラーなどの元のエラーを返す
+        builder
+            .build()
+            .or_else(|e| builder.depth_first(true).build().map_err(|_| e))
+    }
+}
+
+/// 行数などの数値の引数をパース
+fn parse_num(option: &str, value: Option<String>) -> Result<usize, DynError> {
+    let value = value.ok_or_else(|| format!("option requires an argument: {option}"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid number: {option} {value}").into())
+}
+
+/// 正規表現の全体にマッチする文字列をn個生成し、1行ずつoutに出力する
+///
+/// near_missがtrueの場合は、マッチする文字列を1文字だけ変えた、マッチしない文字列を生成する。
+/// 同じseedからは同じ文字列を生成する。
+/// 探索は行ごとに行うため、改行を含む文字列は出力せずに生成し直す
+pub fn generate<W: Write>(
+    regex: &Regex,
+    n: usize,
+    near_miss: bool,
+    seed: u64,
+    mut out: W,
+) -> Result<(), DynError> {
+    const MAX_ATTEMPTS: usize = 1000;
+
+    let mut generator = regex.generator(seed)?;
+    for _ in 0..n {
+        let mut attempts = 0;
+        let text = loop {
+            let text = if near_miss {
+                generator.near_miss()?
+            } else {
+                generator.generate()?
+            };
+            if !text.contains('\n') {
+                break text;
+            }
+            attempts += 1;
+            if attempts >= MAX_ATTEMPTS {
+                return Err("no string without newlines generated".into());
+            }
+        };
+        writeln!(out, "{text}")?;
+    }
+    Ok(())
+}
+
+/// ファイルや標準入力から、正規表現にマッチする行を探索して出力する
+pub struct Searcher<'a, W: Write> {
+    regex: &'a Regex,
+    opts: &'a Options,
+    out: W,
+    color: bool,          // 色付けを行うか
+    with_filename: bool,  // 行の先頭にファイル名を表示するか
+    printed_group: bool,  // 前後の行を表示する場合に、既に行のまとまりを表示したか
+    pub is_matched: bool, // マッチした行が一つでもあったか
+    pub has_error: bool,  // 読み込みに失敗したファイルがあったか
+}
+
+impl<'a, W: Write> Searcher<'a, W> {
+    pub fn new(regex: &'a Regex, opts: &'a Options, color: bool, out: W) -> Self {
+        Searcher {
+            regex,
+            opts,
+            out,
+            color,
+            with_filename: opts.files.len() > 1 || opts.recursive,
+            printed_group: false,
+            is_matched: false,
+            has_error: false,
+        }
+    }
+
+    /// 出力先を返す
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// ファイルやディレクトリを探索
+    ///
+    /// pathが-の場合は標準入力を探索する。
+    /// 読み込みに失敗したファイルは標準エラー出力に表示して読み飛ばすが、
+    /// 出力先への書き込みに失敗した場合はErrを返す
+    pub fn search_path(&mut self, path: &str) -> Result<(), DynError> {
+        let result = if path == "-" {
+            let stdin = io::stdin();
+            self.search_reader(STDIN_NAME, stdin.lock())
+        } else if Path::new(path).is_dir() {
+            if self.opts.recursive {
+                return self.search_dir(Path::new(path));
+            }
+            Err(format!("{path}: is a directory").into())
+        } else {
+            File::open(path)
+                .map_err(|e| format!("{path}: {e}").into())
+                .and_then(|f| self.search_reader(path, BufReader::new(f)))
+        };
+        self.report(result)
+    }
+
+    /// ディレクトリを再帰的に探索
+    ///
+    /// 出力が実行ごとに変わらないよう、エントリはパス順に探索する。
+    /// シンボリックリンクは辿らない
+    fn search_dir(&mut self, dir: &Path) -> Result<(), DynError> {
+        let entries = fs::read_dir(dir).and_then(|entries| {
+            let mut entries = entries.collect::<Result<Vec<_>, _>>()?;
+            entries.sort_by_key(|e| e.path());
+            Ok(entries)
+        });
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => return self.report(Err(format!("{}: {e}", dir.display()).into())),
+        };
+
+        for entry in entries {
+            let path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    self.report(Err(format!("{}: {e}", path.display()).into()))?;
+                    continue;
+                }
+            };
+
+            if file_type.is_dir() {
+                self.search_dir(&path)?;
+            } else if file_type.is_file() {
+                let name = path.display().to_string();
+                self.search_path(&name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 探索中のエラーを標準エラー出力に表示
+    ///
+    /// 出力先への書き込みエラーの場合は、探索を続けられないためErrを返す
+    fn report(&mut self, result: Result<usize, DynError>) -> Result<(), DynError> {
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) if e.downcast_ref::<io::Error>().is_some() => Err(e),
+            Err(e) => {
+                eprintln!("regex_engine: {e}");
+                self.has_error = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// readerから1行ずつ読み込み、マッチした行を出力
+    ///
+    /// nameはファイル名。マッチした行数を返す。
+    /// 各行はUTF-8のバイト列として評価するため、不正なバイトを含む行もそのまま出力する
+    pub fn search_reader<R: BufRead>(
+        &mut self,
+        name: &str,
+        mut reader: R,
+    ) -> Result<usize, DynError> {
+        let opts = self.opts;
+        let show_lines = !opts.count && !opts.files_with_matches;
+        let use_context = show_lines && !opts.only_matching && (opts.after > 0 || opts.before > 0);
+
+        let mut buf = Vec::new();
+        let mut line_no = 0;
+        let mut count = 0;
+        let mut before: VecDeque<(usize, Vec<u8>)> = VecDeque::new(); // 直前の行（行番号, 行）
+        let mut after_left = 0; // 後ろに表示する残りの行数
+        let mut last_printed = None; // 最後に表示した行番号
+
+        loop {
+            buf.clear();
+            let len = reader
+                .read_until(b'\n', &mut buf)
+                .map_err(|e| format!("{name}: {e}"))?;
+            if len == 0 {
+                break;
+            }
+            line_no += 1;
+            let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+
+            let selected = self.regex.is_match_bytes(line)? != opts.invert;
+            if selected {
+                count += 1;
+                self.is_matched = true;
+                if opts.files_with_matches {
+                    self.write_name(name)?;
+                    writeln!(self.out)?;
+                    return Ok(count);
+                }
+                if !show_lines {
+                    continue;
+                }
+
+                if use_context {
+                    // 前のまとまりと離れている場合は区切りを表示
+                    let first = before.front().map_or(line_no, |(n, _)| *n);
+                    let is_separated = match last_printed {
+                        Some(last) => first > last + 1,
+                        None => self.printed_group,
+                    };
+                    if is_separated {
+                        self.write_colored(COLOR_SEP, "--")?;
+                        writeln!(self.out)?;
+                    }
+                    for (n, l) in before.drain(..) {
+                        self.write_line(name, n, &l, false)?;
+                    }
+                    after_left = opts.after;
+                    self.printed_group = true;
+                }
+
+                if opts.only_matching {
+                    self.write_matches(name, line_no, line)?;
+                } else {
+                    self.write_line(name, line_no, line, true)?;
+                }
+                last_printed = Some(line_no);
+            } else if use_context {
+                if after_left > 0 {
+                    self.write_line(name, line_no, line, false)?;
+                    after_left -= 1;
+                    last_printed = Some(line_no);
+                } else if opts.before > 0 {
+                    before.push_back((line_no, line.to_vec()));
+                    if before.len() > opts.before {
+                        before.pop_front();
+                    }
+                }
+            }
+        }
+
+        if opts.count && !opts.files_with_matches {
+            if self.with_filename {
+                self.write_name(name)?;
+                self.write_colored(COLOR_SEP, ":")?;
+            }
+            writeln!(self.out, "{count}")?;
+        }
+
+        Ok(count)
+    }
+
+    /// 行を出力
+    ///
+    /// selectedがtrueの場合はマッチした行、falseの場合は前後の行として出力する
+    fn write_line(
+        &mut self,
+        name: &str,
+        n: usize,
+        line: &[u8],
+        selected: bool,
+    ) -> Result<(), DynError> {
+        self.write_prefix(name, n, selected)?;
+        let mut last = 0;
+        if selected && self.color && !self.opts.invert {
+            for m in self.regex.find_iter_bytes(line) {
+                let m = m?;
+                if m.start() == m.end() {
+                    continue;
+                }
+                self.out.write_all(&line[last..m.start()])?;
+                self.write_colored(COLOR_MATCH, m.as_bytes())?;
+                last = m.end();
+            }
+        }
+        self.out.write_all(&line[last..])?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    /// 行中のマッチした部分のみを、1行ずつ出力
+    fn write_matches(&mut self, name: &str, n: usize, line: &[u8]) -> Result<(), DynError> {
+        if self.opts.invert {
+            return Ok(());
+        }
+        for m in self.regex.find_iter_bytes(line) {
+            let m = m?;
+            if m.start() == m.end() {
+                continue;
+            }
+            self.write_prefix(name, n, true)?;
+            self.write_colored(COLOR_MATCH, m.as_bytes())?;
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+
+    /// ファイル名と行番号を出力
+    fn write_prefix(&mut self, name: &str, n: usize, selected: bool) -> Result<(), DynError> {
+        let sep = if selected { ":" } else { "-" };
+        if self.with_filename {
+            self.write_name(name)?;
+            self.write_colored(COLOR_SEP, sep)?;
+        }
+        if self.opts.line_number {
+            self.write_colored(COLOR_LINE, &n.to_string())?;
+            self.write_colored(COLOR_SEP, sep)?;
+        }
+        Ok(())
+    }
+
+    fn write_name(&mut self, name: &str) -> Result<(), DynError> {
+        self.write_colored(COLOR_FILE, name)
+    }
+
+    /// 色付けが有効な場合は、colorで色付けしてsを出力
+    fn write_colored<S: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        color: &str,
+        s: &S,
+    ) -> Result<(), DynError> {
+        if self.color {
+            self.out.write_all(color.as_bytes())?;
+            self.out.write_all(s.as_ref())?;
+            self.out.write_all(COLOR_RESET.as_bytes())?;
+        } else {
+            self.out.write_all(s.as_ref())?;
+        }
+        Ok(())
+    }
+}
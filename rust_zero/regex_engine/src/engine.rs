@@ -1,26 +1,67 @@
 //! 正規表現エンジン
 
 mod codegen;
+mod dfa;
+mod differential;
+mod equiv;
 mod evaluator;
+mod export;
+mod generate;
+mod optimizer;
 mod parser;
+mod regex;
+mod replace;
+mod set;
+mod stream;
+mod text;
+mod unicode_tables;
 
 use crate::helper::DynError;
+use parser::{Assertion, Class, Look};
+use std::io::{self, Write};
+
+pub use differential::{check_evaluators, check_evaluators_random, DifferentialError};
+pub use equiv::{Comparison, EquivError};
+pub use export::{export_dot, export_json};
+pub use generate::{GenerateError, Generator};
+pub use parser::{ParseError, Span};
+pub use regex::{
+    ByteMatch, ByteMatches, CaptureMatches, Captures, Match, Matches, Regex, RegexBuilder,
+};
+pub use replace::{NoExpand, Replacer, Split};
+pub use set::{RegexSet, SetError, SetMatches};
+pub use stream::{StreamError, StreamMatch, StreamMatches};
 
 #[derive(Debug)]
 pub enum Instruction {
     Char(char),
-    Match,
+    Class(Class),
+    Any,
+    AnyNewline,
+    Assert(Assertion),
+    Save(usize),
+    Match(usize), // パターンの番号。RegexSet以外では0となる
     Jump(usize),
     Split(usize, usize),
+    BackRef(usize, bool), // (グループ番号, 大文字と小文字を区別しないか)
+    Look(Look, usize), // (種類, 部分プログラムの直後のアドレス)。部分プログラムは次のアドレスから始まる
 }
 
 impl std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Instruction::Char(c) => write!(f, "char {c}"),
-            Instruction::Match => write!(f, "match"),
+            Instruction::Class(class) => write!(f, "class {class}"),
+            Instruction::Any => write!(f, "any"),
+            Instruction::AnyNewline => write!(f, "any_nl"),
+            Instruction::Assert(assertion) => write!(f, "assert {assertion}"),
+            Instruction::Save(n) => write!(f, "save {n}"),
+            Instruction::Match(id) => write!(f, "match {id}"),
             Instruction::Jump(addr) => write!(f, "jump {:>04}", addr),
             Instruction::Split(addr1, addr2) => write!(f, "split {:>04}, {:>04}", addr1, addr2),
+            Instruction::BackRef(n, false) => write!(f, "backref {n}"),
+            Instruction::BackRef(n, true) => write!(f, "backref {n} (?i)"),
+            Instruction::Look(look, addr) => write!(f, "look {look}, {:>04}", addr),
         }
     }
 }
@@ -28,27 +69,60 @@ impl std::fmt::Display for Instruction {
 /// 正規表現をパースしてコードを生成し、
 /// ASTと命令列を標準出力に表示
 ///
+/// 表示される命令列は、Regexが探索に用いるものと同じ
+///
 /// # 利用例
 ///
 /// ```
 /// use regex_engine::engine;
-/// engine::print("abc|(de|cd)+");
+/// engine::print("abc|(de|cd)+", false);
 /// ```
 ///
+/// # 引数
+///
+/// show_unoptimizedがtrueの場合は、最適化前と最適化後の命令列を並べて表示
+///
 /// # 返り値
 ///
 /// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合はErrを返す
+pub fn print(expr: &str, show_unoptimized: bool) -> Result<(), DynError> {
+    let regex = Regex::new(expr)?;
+    print_regex(&regex, show_unoptimized, &mut io::stdout().lock())?;
+    Ok(())
+}
+
+/// コンパイル済みの正規表現のASTと命令列をoutに書き出す
+///
+/// RegexBuilderで指定したフラグを反映したものが表示される。
+/// show_unoptimizedがtrueの場合は、最適化前と最適化後の命令列を並べて表示
+///
 /// ```
-pub fn print(expr: &str) -> Result<(), DynError> {
-    println!("expr: {expr}");
-    let ast = parser::parse(expr)?;
-    println!("AST: {:?}", ast);
+/// use regex_engine::engine::{self, Regex};
+/// let mut out = Vec::new();
+/// engine::print_regex(&Regex::new("a|b").unwrap(), false, &mut out).unwrap();
+/// assert!(String::from_utf8(out).unwrap().starts_with("expr: a|b\n"));
+/// ```
+pub fn print_regex<W: Write>(regex: &Regex, show_unoptimized: bool, out: &mut W) -> io::Result<()> {
+    writeln!(out, "expr: {}", regex.as_str())?;
+    writeln!(out, "AST: {:?}", regex.ast)?;
+    if let Some(prefix) = &regex.prefix {
+        writeln!(out, "prefix: {prefix:?}")?;
+    }
 
-    println!();
-    println!("code:");
-    let code = codegen::get_code(&ast)?;
-    for (n, c) in code.iter().enumerate() {
-        println!("{:>04}: {c}", n);
+    if show_unoptimized {
+        writeln!(out)?;
+        writeln!(out, "code (unoptimized):")?;
+        // Regexの生成時に同じコードを生成できているため、ここでは失敗しない
+        let code = codegen::get_search_code(&regex.ast).map_err(io::Error::other)?;
+        for (n, c) in code.iter().enumerate() {
+            writeln!(out, "{:>04}: {c}", n)?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "code:")?;
+    for (n, c) in regex.code.iter().enumerate() {
+        writeln!(out, "{:>04}: {c}", n)?;
     }
     Ok(())
 }
@@ -65,7 +139,8 @@ pub fn print(expr: &str) -> Result<(), DynError> {
 /// # 引数
 ///
 /// expreに正規表現、lineにマッチ対象とする文字列を与える。
-/// is_depthがtrueの場合は深さ優先探索を、falseの場合は幅優先探索を利用
+/// is_depthがtrueの場合は深さ優先探索を、falseの場合は幅優先探索を利用。
+/// lineは文字の配列に変換せず、UTF-8のバイト列のまま評価する
 ///
 /// # 返り値
 ///
@@ -77,6 +152,31 @@ pub fn print(expr: &str) -> Result<(), DynError> {
 pub fn do_matching(expr: &str, line: &str, is_depth: bool) -> Result<bool, DynError> {
     let ast = parser::parse(expr)?;
     let code = codegen::get_code(&ast)?;
-    let line = line.chars().collect::<Vec<char>>();
-    Ok(evaluator::eval(&code, &line, is_depth)?)
+    evaluator::eval(&code, line.as_bytes(), is_depth)
+}
+
+/// 正規表現にマッチする部分文字列を探索
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::engine;
+/// engine::do_searching("b+c$", "abbbc", false);
+/// ```
+///
+/// # 引数
+///
+/// do_matchingと同様だが、do_matchingがlineの先頭からのマッチのみを判定するのに対し、
+/// こちらはlineの任意の位置から始まるマッチを探索する。
+/// 正規表現の先頭に.*?を付加したコードを生成するため、lineの走査は1度で済む
+///
+/// # 返り値
+///
+/// マッチする部分文字列が存在した場合はOk(true)を、存在しない場合はOk(false)を返す
+///
+/// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合はErrを返す
+pub fn do_searching(expr: &str, line: &str, is_depth: bool) -> Result<bool, DynError> {
+    let ast = parser::parse(expr)?;
+    let code = codegen::get_search_code(&ast)?;
+    evaluator::eval(&code, line.as_bytes(), is_depth)
 }
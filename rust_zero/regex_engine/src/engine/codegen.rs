@@ -1,4 +1,7 @@
-use super::{parser::AST, Instruction};
+use super::{
+    parser::{Assertion, Class, Look, AST},
+    Instruction,
+};
 use crate::helper::{safe_add, DynError};
 use std::{
     error::Error,
@@ -11,6 +14,10 @@ pub enum CodeGenError {
     FailStar,
     FailOr,
     FailQuestion,
+    FailSearch,
+    FailRepeat,
+    FailLook,
+    FailSet,
 }
 
 impl Display for CodeGenError {
@@ -21,6 +28,12 @@ impl Display for CodeGenError {
 
 impl Error for CodeGenError {}
 
+/// 生成する命令列の長さの上限
+///
+/// a{1000}{1000}のような回数指定は命令列が非常に長くなるため、
+/// この長さを超える場合はPCOverFlowエラーとする
+const MAX_CODE_LEN: usize = 1 << 20;
+
 /// コード生成器
 #[derive(Default, Debug)]
 struct Generator {
@@ -33,24 +46,36 @@ impl Generator {
     fn gen_code(&mut self, ast: &AST) -> Result<(), DynError> {
         self.gen_expr(ast)?;
         self.inc_pc()?;
-        self.insts.push(Instruction::Match);
+        self.insts.push(Instruction::Match(0));
         Ok(())
     }
 
     /// プログラムカウンタをインクリメント
     fn inc_pc(&mut self) -> Result<(), CodeGenError> {
-        safe_add(&mut self.pc, &1, || CodeGenError::PCOverFlow)
+        safe_add(&mut self.pc, &1, || CodeGenError::PCOverFlow)?;
+        if self.pc > MAX_CODE_LEN {
+            return Err(CodeGenError::PCOverFlow);
+        }
+        Ok(())
     }
 
     /// ASTをパターン分けし、コード生成を行う関数
     fn gen_expr(&mut self, ast: &AST) -> Result<(), DynError> {
         match ast {
             AST::Char(c) => self.gen_char(*c)?,
+            AST::Class(class) => self.gen_class(class)?,
+            AST::Any => self.gen_any(false)?,
+            AST::AnyNewline => self.gen_any(true)?,
+            AST::Assert(assertion) => self.gen_assert(*assertion)?,
             AST::Or(e1, e2) => self.gen_or(e1, e2)?,
-            AST::Plus(e) => self.gen_plus(e)?,
-            AST::Star(e) => self.gen_star(e)?,
-            AST::Question(e) => self.gen_question(e)?,
+            AST::Plus(e, greedy) => self.gen_plus(e, *greedy)?,
+            AST::Star(e, greedy) => self.gen_star(e, *greedy)?,
+            AST::Question(e, greedy) => self.gen_question(e, *greedy)?,
+            AST::Repeat(e, min, max, greedy) => self.gen_repeat(e, *min, *max, *greedy)?,
             AST::Seq(v) => self.gen_seq(v)?,
+            AST::Capture(n, _, e) => self.gen_capture(*n, e)?,
+            AST::BackRef(n, case_insensitive) => self.gen_backref(*n, *case_insensitive)?,
+            AST::Look(look, e) => self.gen_look(*look, e)?,
         }
         Ok(())
     }
@@ -71,6 +96,260 @@ impl Generator {
         Ok(())
     }
 
+    /// class命令生成関数
+    fn gen_class(&mut self, class: &Class) -> Result<(), DynError> {
+        let inst = Instruction::Class(class.clone());
+        self.insts.push(inst);
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// any命令生成関数
+    ///
+    /// newlineがtrueの場合は、改行にもマッチするany_nl命令を生成
+    fn gen_any(&mut self, newline: bool) -> Result<(), DynError> {
+        if newline {
+            self.insts.push(Instruction::AnyNewline);
+        } else {
+            self.insts.push(Instruction::Any);
+        }
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// assert命令生成関数
+    fn gen_assert(&mut self, assertion: Assertion) -> Result<(), DynError> {
+        self.insts.push(Instruction::Assert(assertion));
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// backref命令生成関数
+    fn gen_backref(&mut self, n: usize, case_insensitive: bool) -> Result<(), DynError> {
+        self.insts.push(Instruction::BackRef(n, case_insensitive));
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// 先読みと後読みのコードを生成する
+    ///
+    /// 以下のようなコードを生成する。
+    /// look命令は、L1から始まりmatchで終わる部分プログラムを評価し、
+    /// 条件が成り立つ場合にL2へ進む
+    ///
+    /// ```text
+    ///     look L2
+    /// L1: eのコード
+    ///     match
+    /// L2:
+    /// ```
+    fn gen_look(&mut self, look: Look, e: &AST) -> Result<(), DynError> {
+        // [look L2]のコード生成。L2は部分プログラムの生成後に設定する
+        let look_addr = self.pc;
+        self.insts.push(Instruction::Look(look, 0));
+        self.inc_pc()?;
+
+        // [L1: e]と[match]のコード生成
+        self.gen_expr(e)?;
+        self.insts.push(Instruction::Match(0));
+        self.inc_pc()?;
+
+        // Look命令のL2の値を設定
+        if let Some(Instruction::Look(_, l2)) = self.insts.get_mut(look_addr) {
+            *l2 = self.pc;
+            Ok(())
+        } else {
+            Err(Box::new(CodeGenError::FailLook))
+        }
+    }
+
+    /// 探索用のコードを生成する関数の入り口
+    ///
+    /// 先頭に.*?相当のコードを付加し、任意の位置から始まるマッチを
+    /// 1度の評価で探索できるようにする。
+    /// 改行も読み飛ばせるよう、anyではなくany_nlを用いる。
+    /// また、マッチした範囲を得るため、正規表現のコードの前後で
+    /// スロット0と1に文字位置を記録する
+    ///
+    /// ```text
+    /// L1: split L3, L2
+    /// L2: any_nl
+    ///     jump L1
+    /// L3: save 0
+    ///     正規表現のコード
+    ///     save 1
+    ///     match
+    /// ```
+    fn gen_search_code(&mut self, ast: &AST) -> Result<(), DynError> {
+        self.gen_skip_loop()?;
+        self.gen_save(0)?;
+        self.gen_expr(ast)?;
+        self.gen_save(1)?;
+        self.inc_pc()?;
+        self.insts.push(Instruction::Match(0));
+        Ok(())
+    }
+
+    /// 複数の正規表現を1度の評価で探索するコードを生成する関数の入り口
+    ///
+    /// 探索用のコードと同様に先頭に.*?相当のコードを付加し、
+    /// 各正規表現をsplitで並べる。i番目の正規表現のコードの後には、match iを置く。
+    /// マッチした範囲は求めないため、saveは生成しない
+    ///
+    /// ```text
+    /// L1: split L3, L2
+    /// L2: any_nl
+    ///     jump L1
+    /// L3: split P0, S1
+    /// P0: 0番目の正規表現のコード
+    ///     match 0
+    /// S1: split P1, S2
+    /// P1: 1番目の正規表現のコード
+    ///     match 1
+    /// S2: 2番目（最後）の正規表現のコード
+    ///     match 2
+    /// ```
+    fn gen_set_code(&mut self, asts: &[AST]) -> Result<(), DynError> {
+        self.gen_skip_loop()?;
+
+        for (id, ast) in asts.iter().enumerate() {
+            // 最後の正規表現以外は、[split Pi, Si+1]のコードを生成
+            let split_addr = self.pc;
+            let is_last = id + 1 == asts.len();
+            if !is_last {
+                self.inc_pc()?;
+                self.insts.push(Instruction::Split(self.pc, 0));
+            }
+
+            // [Pi: 正規表現のコード]と[match i]のコード生成
+            self.gen_expr(ast)?;
+            self.inc_pc()?;
+            self.insts.push(Instruction::Match(id));
+
+            // Split命令のSi+1の値を設定
+            if !is_last {
+                if let Some(Instruction::Split(_, next)) = self.insts.get_mut(split_addr) {
+                    *next = self.pc;
+                } else {
+                    return Err(Box::new(CodeGenError::FailSet));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 探索用のコードの先頭に付加する、.*?相当のコードを生成する
+    ///
+    /// ```text
+    /// L1: split L3, L2
+    /// L2: any_nl
+    ///     jump L1
+    /// L3:
+    /// ```
+    fn gen_skip_loop(&mut self) -> Result<(), DynError> {
+        // [L1: split L3, L2]のコード生成
+        // 非貪欲にするため、正規表現本体を優先する
+        let split_addr = self.pc;
+        self.inc_pc()?;
+        self.insts.push(Instruction::Split(0, self.pc));
+
+        // [L2: any_nl]と[jump L1]のコード生成
+        self.insts.push(Instruction::AnyNewline);
+        self.inc_pc()?;
+        self.insts.push(Instruction::Jump(split_addr));
+        self.inc_pc()?;
+
+        // Split命令のL3の値を設定
+        if let Some(Instruction::Split(l3, _)) = self.insts.get_mut(split_addr) {
+            *l3 = self.pc;
+            Ok(())
+        } else {
+            Err(Box::new(CodeGenError::FailSearch))
+        }
+    }
+
+    /// 回数指定{n,m}のコードを生成する
+    ///
+    /// e{n,m}は、eをn回繰り返した後に、e?をm - n回繰り返すコードに展開する。
+    /// ただし、e?の繰り返しは一つ目が失敗した時点で残りも読み飛ばせるよう、
+    /// すべてのsplitの飛び先を末尾にする。
+    /// 上限のないe{n,}は、eをn回繰り返した後にe*を続ける。
+    /// 非貪欲な場合は、splitの飛び先の順序を入れ替える
+    ///
+    /// ```text
+    /// e{2,4}の場合
+    ///
+    ///     eのコード
+    ///     eのコード
+    ///     split L1, L3
+    /// L1: eのコード
+    ///     split L2, L3
+    /// L2: eのコード
+    /// L3:
+    /// ```
+    fn gen_repeat(
+        &mut self,
+        e: &AST,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    ) -> Result<(), DynError> {
+        for _ in 0..min {
+            let pc = self.pc;
+            self.gen_expr(e)?;
+            if pc == self.pc {
+                // eが命令を生成しない場合は、何度繰り返しても同じ
+                return Ok(());
+            }
+        }
+
+        let max = if let Some(max) = max {
+            max
+        } else {
+            return self.gen_star(e, greedy);
+        };
+
+        // [split L1, L3]とeのコードをm - n回生成
+        let mut split_addrs = Vec::new();
+        for _ in min..max {
+            split_addrs.push(self.pc);
+            self.inc_pc()?;
+            self.insts.push(Instruction::Split(self.pc, 0));
+            self.gen_expr(e)?;
+        }
+
+        // Split命令のL3の値を設定
+        for split_addr in split_addrs {
+            self.set_split_dst(split_addr, greedy, CodeGenError::FailRepeat)?;
+        }
+
+        Ok(())
+    }
+
+    /// キャプチャグループのコードを生成する
+    ///
+    /// 以下のようなコードを生成し、n番目のグループの範囲を
+    /// スロット2nと2n + 1に記録する
+    ///
+    /// ```text
+    ///     save 2n
+    ///     eのコード
+    ///     save 2n + 1
+    /// ```
+    fn gen_capture(&mut self, n: usize, e: &AST) -> Result<(), DynError> {
+        self.gen_save(2 * n)?;
+        self.gen_expr(e)?;
+        self.gen_save(2 * n + 1)?;
+        Ok(())
+    }
+
+    /// save命令生成関数
+    fn gen_save(&mut self, n: usize) -> Result<(), DynError> {
+        self.insts.push(Instruction::Save(n));
+        self.inc_pc()?;
+        Ok(())
+    }
+
     /// OR演算子のコードを生成する
     ///
     /// 以下のようなコードを生成
@@ -118,6 +397,27 @@ impl Generator {
         Ok(())
     }
 
+    /// split_addrにあるSplit命令の2番目の飛び先を現在のpcに設定する
+    ///
+    /// 非貪欲な場合は、1番目と2番目の飛び先を入れ替え、
+    /// 繰り返しを抜ける側を優先させる
+    fn set_split_dst(
+        &mut self,
+        split_addr: usize,
+        greedy: bool,
+        err: CodeGenError,
+    ) -> Result<(), DynError> {
+        if let Some(Instruction::Split(l1, l2)) = self.insts.get_mut(split_addr) {
+            *l2 = self.pc;
+            if !greedy {
+                std::mem::swap(l1, l2);
+            }
+            Ok(())
+        } else {
+            Err(Box::new(err))
+        }
+    }
+
     /// Plus演算子のコードを生成する
     ///
     /// 以下のようなコードを生成
@@ -127,7 +427,9 @@ impl Generator {
     ///     split L1, L2
     /// L2:
     /// ```
-    fn gen_plus(&mut self, e1: &AST) -> Result<(), DynError> {
+    ///
+    /// 非貪欲な場合は、split L2, L1となる
+    fn gen_plus(&mut self, e1: &AST, greedy: bool) -> Result<(), DynError> {
         let split_addr = self.pc;
 
         // [L1: e1]のコード生成
@@ -135,7 +437,11 @@ impl Generator {
         self.inc_pc()?;
 
         // [split L1, L2] のコード生成
-        let split = Instruction::Split(split_addr, self.pc);
+        let split = if greedy {
+            Instruction::Split(split_addr, self.pc)
+        } else {
+            Instruction::Split(self.pc, split_addr)
+        };
         self.insts.push(split);
 
         Ok(())
@@ -151,7 +457,9 @@ impl Generator {
     ///     jump L1
     /// L3:
     /// ```
-    fn gen_star(&mut self, e1: &AST) -> Result<(), DynError> {
+    ///
+    /// 非貪欲な場合は、split L3, L2となる
+    fn gen_star(&mut self, e1: &AST, greedy: bool) -> Result<(), DynError> {
         // [split L2, L3] のコード生成
         let split_addr = self.pc;
         self.inc_pc()?;
@@ -166,13 +474,7 @@ impl Generator {
         self.inc_pc()?;
 
         // Split命令のL3の値を設定
-        if let Some(Instruction::Split(_, l3)) = self.insts.get_mut(split_addr) {
-            *l3 = self.pc;
-        } else {
-            return Err(Box::new(CodeGenError::FailOr));
-        }
-
-        Ok(())
+        self.set_split_dst(split_addr, greedy, CodeGenError::FailStar)
     }
 
     /// Question演算子のコードを生成する
@@ -184,7 +486,9 @@ impl Generator {
     /// L1: e1のコード
     /// L2:
     /// ```
-    fn gen_question(&mut self, e1: &AST) -> Result<(), DynError> {
+    ///
+    /// 非貪欲な場合は、split L2, L1となる
+    fn gen_question(&mut self, e1: &AST, greedy: bool) -> Result<(), DynError> {
         // [split L1, L2] のコード生成
         let split_addr = self.pc;
         self.inc_pc()?;
@@ -195,13 +499,7 @@ impl Generator {
         self.gen_expr(e1)?;
 
         // Split命令のL2の値を設定
-        if let Some(Instruction::Split(_, l2)) = self.insts.get_mut(split_addr) {
-            *l2 = self.pc;
-        } else {
-            return Err(Box::new(CodeGenError::FailOr));
-        }
-
-        Ok(())
+        self.set_split_dst(split_addr, greedy, CodeGenError::FailQuestion)
     }
 }
 
@@ -210,3 +508,19 @@ pub fn get_code(ast: &AST) -> Result<Vec<Instruction>, DynError> {
     generator.gen_code(ast)?;
     Ok(generator.insts)
 }
+
+/// 文字列中の任意の位置から始まるマッチを探索するコードを生成
+pub fn get_search_code(ast: &AST) -> Result<Vec<Instruction>, DynError> {
+    let mut generator = Generator::default();
+    generator.gen_search_code(ast)?;
+    Ok(generator.insts)
+}
+
+/// 複数の正規表現のマッチを1度に探索するコードを生成
+///
+/// i番目の正規表現にマッチした場合は、Match(i)命令に到達する
+pub fn get_set_code(asts: &[AST]) -> Result<Vec<Instruction>, DynError> {
+    let mut generator = Generator::default();
+    generator.gen_set_code(asts)?;
+    Ok(generator.insts)
+}
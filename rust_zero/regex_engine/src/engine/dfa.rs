@@ -0,0 +1,333 @@
+//! 遅延DFA（Lazy DFA）によるマッチング
+//!
+//! 命令列をNFAとみなし、部分集合構成法によってDFAの状態を必要になった時点で生成する。
+//! 生成した状態と遷移はキャッシュし、以降の評価で使い回すため、
+//! 同じ正規表現で多数の行を評価する場合は、ほぼ1文字1回の表引きで評価できる。
+//!
+//! マッチの有無のみを判定し、マッチした範囲は求めない
+use super::{evaluator, parser::Assertion, Instruction};
+use crate::helper::{safe_add, DynError};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// ASCII文字の遷移先を配列で保持するための大きさ
+const ASCII_LEN: usize = 128;
+
+#[derive(Debug)]
+pub enum DfaError {
+    PCOverFlow,
+    InvalidPC,
+    Unsupported, // is_supportedがfalseとなる命令
+}
+
+impl Display for DfaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DfaError: {:?}", self)
+    }
+}
+
+impl Error for DfaError {}
+
+/// ^のような、直前の文字で判定するアサーションのための現在位置の情報
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct Context {
+    pub(super) at_begin: bool, // 文字列の先頭か
+    pub(super) after_nl: bool, // 直前の文字が改行か
+}
+
+/// $のような、直後の文字で判定するアサーションのための次の文字の情報
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Lookahead {
+    Unknown, // まだ読み込んでいない
+    End,     // 文字列の末尾
+    Newline, // 改行
+}
+
+/// DFAの状態
+///
+/// NFAの状態集合として、文字を消費する命令と、
+/// 次の文字を読むまで判定できない$のアサーションのアドレスを保持する
+#[derive(Debug)]
+struct State {
+    pcs: Vec<usize>,              // NFAの状態集合（昇順）
+    is_match: bool,               // Match命令に到達しているか
+    is_match_at_end: bool,        // 文字列の末尾であればマッチするか
+    ctx: Context,                 // 状態集合を求めた位置の情報
+    ascii: Vec<Option<usize>>,    // ASCII文字による遷移先の状態
+    others: HashMap<char, usize>, // ASCII以外の文字による遷移先の状態
+}
+
+/// NFAの状態集合と、Match命令に到達しているか、位置の情報の組
+pub(super) type StateKey = (Vec<usize>, bool, Context);
+
+/// 遅延DFA
+#[derive(Debug)]
+pub struct LazyDfa {
+    states: Vec<State>,
+    cache: HashMap<StateKey, usize>, // NFAの状態集合からDFAの状態への対応
+    limit: usize,                    // キャッシュする状態数の上限
+}
+
+impl LazyDfa {
+    /// 命令列instを評価する遅延DFAを生成
+    ///
+    /// limitはキャッシュする状態数の上限で、メモリ使用量の上限となる。
+    /// DFAで評価できない命令が含まれる場合はNoneを返す
+    pub fn new(inst: &[Instruction], limit: usize) -> Option<LazyDfa> {
+        if !inst.iter().all(is_supported) {
+            return None;
+        }
+
+        Some(LazyDfa {
+            states: Vec::new(),
+            cache: HashMap::new(),
+            limit,
+        })
+    }
+
+    /// 命令列instを用いて、文字列lineにマッチするかを判定
+    ///
+    /// マッチした場合はOk(Some(true))を、マッチしなかった場合はOk(Some(false))を返す。
+    /// 状態数が上限を超えた場合はキャッシュを破棄してOk(None)を返すため、
+    /// 呼び出し側は他の評価器で評価し直す必要がある
+    pub fn is_match<I>(&mut self, inst: &[Instruction], line: I) -> Result<Option<bool>, DynError>
+    where
+        I: Iterator<Item = char>,
+    {
+        let ctx = Context {
+            at_begin: true,
+            after_nl: false,
+        };
+        let mut s = match self.get_state(inst, &[0], ctx, false)? {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+
+        for c in line {
+            let state = &self.states[s];
+            if state.is_match {
+                return Ok(Some(true));
+            }
+            if state.pcs.is_empty() {
+                // どの命令にも遷移できないため、以降もマッチしない
+                return Ok(Some(false));
+            }
+
+            s = match self.next_state(inst, s, c)? {
+                Some(s) => s,
+                None => return Ok(None),
+            };
+        }
+
+        let state = &self.states[s];
+        Ok(Some(state.is_match || state.is_match_at_end))
+    }
+
+    /// 状態sから文字cで遷移する先の状態を返す
+    ///
+    /// 遷移先がキャッシュにない場合は、部分集合構成法により新たに生成する
+    fn next_state(
+        &mut self,
+        inst: &[Instruction],
+        s: usize,
+        c: char,
+    ) -> Result<Option<usize>, DynError> {
+        let state = &self.states[s];
+        let cached = if (c as usize) < ASCII_LEN {
+            state.ascii[c as usize]
+        } else {
+            state.others.get(&c).copied()
+        };
+        if cached.is_some() {
+            return Ok(cached);
+        }
+
+        // 次の文字が改行の場合は、判定待ちの(?m)$が成り立つため、その先の命令も加える
+        let mut pcs = state.pcs.clone();
+        let mut is_match = false;
+        if c == '\n' {
+            let ends = pending(inst, &state.pcs, Lookahead::Newline);
+            let (extra, m) = closure(inst, &ends, state.ctx, Lookahead::Newline)?;
+            pcs.extend(extra);
+            is_match = m; // 改行の直前でマッチ
+        }
+
+        // cにマッチする命令の次の命令から、遷移先の状態集合を求める
+        let mut roots = Vec::new();
+        for pc in pcs.iter() {
+            if evaluator::is_match_char(&inst[*pc], c) {
+                let mut next = *pc;
+                safe_add(&mut next, &1, || Box::new(DfaError::PCOverFlow))?;
+                roots.push(next);
+            }
+        }
+
+        let ctx = Context {
+            at_begin: false,
+            after_nl: c == '\n',
+        };
+        let next = match self.get_state(inst, &roots, ctx, is_match)? {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+
+        let state = &mut self.states[s];
+        if (c as usize) < ASCII_LEN {
+            state.ascii[c as usize] = Some(next);
+        } else {
+            state.others.insert(c, next);
+        }
+        Ok(Some(next))
+    }
+
+    /// rootsから文字を消費せずに到達できる状態集合に対応する状態を返す
+    ///
+    /// ctxは現在位置の情報。matchedがtrueの場合は、既にマッチしている状態とする。
+    /// 状態数が上限を超えた場合はキャッシュを破棄してNoneを返す
+    fn get_state(
+        &mut self,
+        inst: &[Instruction],
+        roots: &[usize],
+        ctx: Context,
+        matched: bool,
+    ) -> Result<Option<usize>, DynError> {
+        let (pcs, is_match) = closure(inst, roots, ctx, Lookahead::Unknown)?;
+        let key = (pcs, is_match || matched, ctx);
+        if let Some(s) = self.cache.get(&key) {
+            return Ok(Some(*s));
+        }
+
+        if self.states.len() >= self.limit {
+            self.states.clear();
+            self.cache.clear();
+            return Ok(None);
+        }
+
+        // 判定待ちの$を、文字列の末尾として評価
+        let ends = pending(inst, &key.0, Lookahead::End);
+        let (_, is_match_at_end) = closure(inst, &ends, ctx, Lookahead::End)?;
+
+        let s = self.states.len();
+        self.states.push(State {
+            pcs: key.0.clone(),
+            is_match: key.1,
+            is_match_at_end,
+            ctx,
+            ascii: vec![None; ASCII_LEN],
+            others: HashMap::new(),
+        });
+        self.cache.insert(key, s);
+        Ok(Some(s))
+    }
+}
+
+/// DFAで評価できる命令かを判定
+pub(super) fn is_supported(inst: &Instruction) -> bool {
+    match inst {
+        Instruction::Char(_)
+        | Instruction::Class(_)
+        | Instruction::Any
+        | Instruction::AnyNewline
+        | Instruction::Assert(
+            Assertion::Begin | Assertion::End | Assertion::LineBegin | Assertion::LineEnd,
+        )
+        | Instruction::Save(_)
+        | Instruction::Match(_)
+        | Instruction::Jump(_)
+        | Instruction::Split(_, _) => true,
+        // 後方参照はグループにマッチした文字列を覚えておく必要があり、有限の状態では表せない
+        Instruction::BackRef(_, _) => false,
+        // \bは前後の文字の、先読みと後読みは任意の長さの文字列の判定が必要なため、状態に含めない
+        Instruction::Assert(Assertion::WordBoundary | Assertion::NotWordBoundary)
+        | Instruction::Look(_, _) => false,
+    }
+}
+
+/// 状態集合pcsのうち、次の文字がaheadであれば成り立つ$のアサーションのアドレスを返す
+pub(super) fn pending(inst: &[Instruction], pcs: &[usize], ahead: Lookahead) -> Vec<usize> {
+    pcs.iter()
+        .copied()
+        .filter(|pc| match inst[*pc] {
+            Instruction::Assert(Assertion::End) => ahead == Lookahead::End,
+            Instruction::Assert(Assertion::LineEnd) => ahead != Lookahead::Unknown,
+            _ => false,
+        })
+        .collect()
+}
+
+/// rootsから文字を消費せずに到達できる命令を求める
+///
+/// 文字を消費する命令と、判定待ちの$のアドレスを昇順に並べたものと、
+/// Match命令に到達したかを返す。
+/// ctxは現在位置の情報、aheadは次の文字の情報で、
+/// 次の文字が分からない場合は$を判定待ちとする
+pub(super) fn closure(
+    inst: &[Instruction],
+    roots: &[usize],
+    ctx: Context,
+    ahead: Lookahead,
+) -> Result<(Vec<usize>, bool), DynError> {
+    let mut visited = vec![false; inst.len()];
+    let mut pcs = Vec::new();
+    let mut is_match = false;
+    let mut stack = roots.to_vec();
+
+    while let Some(pc) = stack.pop() {
+        if let Some(v) = visited.get_mut(pc) {
+            if *v {
+                continue;
+            }
+            *v = true;
+        } else {
+            return Err(Box::new(DfaError::InvalidPC));
+        }
+
+        let mut next = pc;
+        safe_add(&mut next, &1, || Box::new(DfaError::PCOverFlow))?;
+        match &inst[pc] {
+            Instruction::Char(_)
+            | Instruction::Class(_)
+            | Instruction::Any
+            | Instruction::AnyNewline => pcs.push(pc),
+            Instruction::Match(_) => is_match = true,
+            Instruction::Assert(assertion) => {
+                let satisfied = match assertion {
+                    Assertion::Begin => Some(ctx.at_begin),
+                    Assertion::LineBegin => Some(ctx.at_begin || ctx.after_nl),
+                    Assertion::End => match ahead {
+                        Lookahead::Unknown => None,
+                        ahead => Some(ahead == Lookahead::End),
+                    },
+                    Assertion::LineEnd => match ahead {
+                        Lookahead::Unknown => None,
+                        _ => Some(true),
+                    },
+                    Assertion::WordBoundary | Assertion::NotWordBoundary => {
+                        return Err(Box::new(DfaError::Unsupported))
+                    }
+                };
+                match satisfied {
+                    Some(true) => stack.push(next),
+                    Some(false) => (),
+                    None => pcs.push(pc), // 次の文字を読むまで判定待ち
+                }
+            }
+            Instruction::Save(_) => stack.push(next),
+            Instruction::Jump(addr) => stack.push(*addr),
+            Instruction::Split(addr1, addr2) => {
+                stack.push(*addr1);
+                stack.push(*addr2);
+            }
+            // LazyDfa::newで除外しているため、ここには到達しない
+            Instruction::BackRef(_, _) | Instruction::Look(_, _) => {
+                return Err(Box::new(DfaError::Unsupported))
+            }
+        }
+    }
+
+    pcs.sort_unstable();
+    Ok((pcs, is_match))
+}
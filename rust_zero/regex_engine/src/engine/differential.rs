@@ -0,0 +1,473 @@
+//! 評価器の差分テスト
+//!
+//! ランダムに生成したASTと入力文字列に対して、深さ優先探索、幅優先探索、遅延DFAの結果が
+//! 一致するかを検査する。一致しない場合は、一致しないまま小さくできなくなるまで
+//! ASTと入力文字列を縮小し、最小の正規表現と入力文字列の組を報告する
+use super::{
+    codegen,
+    dfa::LazyDfa,
+    evaluator::{self, EvalConfig, EvalError},
+    generate::Rng,
+    optimizer,
+    parser::{self, Assertion, Class, Look, AST},
+    text, Instruction,
+};
+use crate::helper::DynError;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 深さ優先探索で実行する命令数の上限
+///
+/// 空の繰り返しは評価器が打ち切るため、上限を超えるのは指数時間のバックトラックによるもので、
+/// 結果の誤りではない。そのため上限を超えた評価器の結果は比較しない
+const STEP_LIMIT: usize = 100_000;
+
+/// 遅延DFAでキャッシュする状態数の上限
+const DFA_STATE_LIMIT: usize = 1000;
+
+/// 生成するASTの深さの上限
+const MAX_DEPTH: usize = 4;
+
+/// 1つのASTに対して生成する入力文字列の数
+const INPUTS_PER_AST: usize = 8;
+
+/// 生成する入力文字列の文字数の上限
+const MAX_INPUT_LEN: usize = 8;
+
+/// ASTと入力文字列に用いる文字
+///
+/// \bや(?m)^の判定、大文字と小文字の区別、マルチバイト文字の位置の扱いを検査できるよう選ぶ
+const ALPHABET: [char; 6] = ['a', 'b', 'A', ' ', '\n', 'あ'];
+
+const ASSERTIONS: [Assertion; 6] = [
+    Assertion::Begin,
+    Assertion::End,
+    Assertion::LineBegin,
+    Assertion::LineEnd,
+    Assertion::WordBoundary,
+    Assertion::NotWordBoundary,
+];
+
+#[derive(Debug)]
+pub enum DifferentialError {
+    Mismatch(String, String, String), // (正規表現, 入力文字列, 各評価器の結果)
+}
+
+impl Display for DifferentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DifferentialError::Mismatch(expr, input, results) => write!(
+                f,
+                "DifferentialError: evaluators disagree: expr = {expr:?}, input = {input:?}: {results}"
+            ),
+        }
+    }
+}
+
+impl Error for DifferentialError {}
+
+/// 正規表現exprと文字列inputに対して、全ての評価器の探索結果が一致するかを検査
+///
+/// 深さ優先探索（メモ化の有無）と幅優先探索を、最適化前と最適化後の命令列でそれぞれ評価し、
+/// 全てのキャプチャグループの位置を比較する。遅延DFAで評価できる場合は、マッチするかも比較する。
+/// 深さ優先探索が命令数の上限を超えた場合は、その結果は比較しない。
+/// 一致しない場合はDifferentialError::Mismatchを返す。
+/// 後方参照を含む場合は、幅優先探索で評価できないためErrを返す
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::engine;
+/// engine::check_evaluators("(a|ab)(c|bcd)(d*)", "abcd").unwrap();
+/// ```
+pub fn check_evaluators(expr: &str, input: &str) -> Result<(), DynError> {
+    let ast = parser::parse(expr)?;
+    match compare(&ast, input)? {
+        Some(results) => Err(Box::new(DifferentialError::Mismatch(
+            expr.to_string(),
+            input.to_string(),
+            results,
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// seedから生成したcases個のASTと、それぞれに対して生成した入力文字列で、
+/// 全ての評価器の探索結果が一致するかを検査
+///
+/// 後方参照は幅優先探索で評価できないため生成しない。
+/// 一致しない場合は、ASTと入力文字列を縮小した上でDifferentialError::Mismatchを返す
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::engine;
+/// engine::check_evaluators_random(42, 100).unwrap();
+/// ```
+pub fn check_evaluators_random(seed: u64, cases: usize) -> Result<(), DynError> {
+    let mut generator = AstGenerator {
+        rng: Rng::new(seed),
+        ncap: 0,
+    };
+    for _ in 0..cases {
+        generator.ncap = 0;
+        let ast = generator.gen(MAX_DEPTH);
+        for _ in 0..INPUTS_PER_AST {
+            let input = generator.input();
+            if let Some(results) = compare(&ast, &input)? {
+                let (ast, input, results) = shrink(ast, &input, results)?;
+                return Err(Box::new(DifferentialError::Mismatch(
+                    ast.to_string(),
+                    input,
+                    results,
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// astと文字列inputに対して、各評価器で探索した結果を比較
+///
+/// 幅優先探索で最適化前の命令列を評価した結果を基準とし、
+/// 一致しない場合は各評価器の結果を並べた文字列を返す
+fn compare(ast: &AST, input: &str) -> Result<Option<String>, DynError> {
+    let line = input.as_bytes();
+    let code = codegen::get_search_code(ast)?;
+    let optimized = optimizer::optimize(codegen::get_search_code(ast)?)?;
+    let nslots = code
+        .iter()
+        .filter_map(|i| match i {
+            Instruction::Save(n) => Some(n + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let config = EvalConfig {
+        step_limit: Some(STEP_LIMIT),
+        ..EvalConfig::default()
+    };
+    let memoize = EvalConfig {
+        memoize: true,
+        ..config
+    };
+
+    let expected = evaluator::eval_slots(&code, line, 0, nslots, false, &config)?;
+    let mut results = vec![("breadth-first", format!("{expected:?}"))];
+    let mut agree = true;
+
+    let evaluators = [
+        ("depth-first", &code, true, &config),
+        ("depth-first (memoize)", &code, true, &memoize),
+        ("breadth-first (optimized)", &optimized, false, &config),
+        ("depth-first (optimized)", &optimized, true, &config),
+    ];
+    for (name, inst, is_depth, config) in evaluators {
+        match evaluator::eval_slots(inst, line, 0, nslots, is_depth, config) {
+            Ok(slots) => {
+                agree &= slots == expected;
+                results.push((name, format!("{slots:?}")));
+            }
+            // 命令数の上限を超えた場合は、結果を比較しない
+            Err(e) if matches!(e.downcast_ref(), Some(EvalError::StepLimitExceeded(_))) => {
+                results.push((name, "skipped (step limit exceeded)".to_string()));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // 遅延DFAはマッチするかのみを判定する
+    for (name, inst) in [("lazy DFA", &code), ("lazy DFA (optimized)", &optimized)] {
+        if let Some(mut dfa) = LazyDfa::new(inst, DFA_STATE_LIMIT) {
+            if let Some(is_match) = dfa.is_match(inst, text::chars(line))? {
+                agree &= is_match == expected.is_some();
+                results.push((name, format!("{is_match}")));
+            }
+        }
+    }
+
+    if agree {
+        return Ok(None);
+    }
+    let results = results
+        .iter()
+        .map(|(name, result)| format!("{name}: {result}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Ok(Some(results))
+}
+
+/// 評価器の結果が一致しないastと文字列inputを、一致しないまま縮小する
+///
+/// 小さくした候補のうち結果が一致しないものがある限り、その候補に置き換えることを繰り返す。
+/// 候補は常に元より小さいため、繰り返しは必ず停止する
+fn shrink(
+    mut ast: AST,
+    input: &str,
+    mut results: String,
+) -> Result<(AST, String, String), DynError> {
+    let mut input = input.chars().collect::<Vec<_>>();
+    'shrink: loop {
+        let text = input.iter().collect::<String>();
+        for mut candidate in shrink_ast(&ast) {
+            renumber(&mut candidate, &mut 0);
+            if let Some(r) = compare(&candidate, &text)? {
+                ast = candidate;
+                results = r;
+                continue 'shrink;
+            }
+        }
+
+        for candidate in shrink_input(&input) {
+            let text = candidate.iter().collect::<String>();
+            if let Some(r) = compare(&ast, &text)? {
+                input = candidate;
+                results = r;
+                continue 'shrink;
+            }
+        }
+
+        return Ok((ast, input.into_iter().collect(), results));
+    }
+}
+
+/// astを1箇所だけ小さくしたASTの候補を返す
+///
+/// 式を子の式に置き換える、Seqの要素を削除する、繰り返す回数を減らす、
+/// 非貪欲を貪欲にする、1文字にマッチする式を文字aにする、のいずれかを行う
+fn shrink_ast(ast: &AST) -> Vec<AST> {
+    let mut candidates = Vec::new();
+    match ast {
+        AST::Plus(e, _)
+        | AST::Star(e, _)
+        | AST::Question(e, _)
+        | AST::Repeat(e, _, _, _)
+        | AST::Capture(_, _, e)
+        | AST::Look(_, e) => candidates.push(e.as_ref().clone()),
+        AST::Or(e1, e2) => candidates.extend([e1.as_ref().clone(), e2.as_ref().clone()]),
+        AST::Seq(v) => candidates.extend(v.iter().cloned()),
+        AST::Char('a') => (),
+        AST::Char(_) | AST::Class(_) | AST::Any | AST::AnyNewline => {
+            candidates.push(AST::Char('a'))
+        }
+        AST::Assert(_) | AST::BackRef(_, _) => (),
+    }
+
+    match ast {
+        AST::Plus(e, greedy) => shrink_quantifier(e, *greedy, &mut candidates, AST::Plus),
+        AST::Star(e, greedy) => shrink_quantifier(e, *greedy, &mut candidates, AST::Star),
+        AST::Question(e, greedy) => shrink_quantifier(e, *greedy, &mut candidates, AST::Question),
+        AST::Repeat(e, min, max, greedy) => {
+            if *min > 0 {
+                candidates.push(AST::Repeat(e.clone(), min - 1, *max, *greedy));
+            }
+            match max {
+                Some(max) if max > min => {
+                    candidates.push(AST::Repeat(e.clone(), *min, Some(max - 1), *greedy))
+                }
+                Some(_) => (),
+                None => candidates.push(AST::Repeat(e.clone(), *min, Some(*min), *greedy)),
+            }
+            shrink_quantifier(e, *greedy, &mut candidates, |e, g| {
+                AST::Repeat(e, *min, *max, g)
+            });
+        }
+        AST::Or(e1, e2) => {
+            for e in shrink_ast(e1) {
+                candidates.push(AST::Or(Box::new(e), e2.clone()));
+            }
+            for e in shrink_ast(e2) {
+                candidates.push(AST::Or(e1.clone(), Box::new(e)));
+            }
+        }
+        AST::Seq(v) => {
+            if v.len() > 1 {
+                for i in 0..v.len() {
+                    let mut v = v.clone();
+                    v.remove(i);
+                    candidates.push(AST::Seq(v));
+                }
+            }
+            for (i, e) in v.iter().enumerate() {
+                for e in shrink_ast(e) {
+                    let mut v = v.clone();
+                    v[i] = e;
+                    candidates.push(AST::Seq(v));
+                }
+            }
+        }
+        AST::Capture(n, name, e) => {
+            for e in shrink_ast(e) {
+                candidates.push(AST::Capture(*n, name.clone(), Box::new(e)));
+            }
+        }
+        AST::Look(look, e) => {
+            for e in shrink_ast(e) {
+                // 後読みがマッチする文字数の範囲は、縮小後の式から求め直す
+                let look = match (look, e.match_len()) {
+                    (Look::Behind(_, _), (min, Some(max))) => Look::Behind(min, max),
+                    (Look::NegativeBehind(_, _), (min, Some(max))) => {
+                        Look::NegativeBehind(min, max)
+                    }
+                    (Look::Behind(_, _) | Look::NegativeBehind(_, _), (_, None)) => continue,
+                    (look, _) => *look,
+                };
+                candidates.push(AST::Look(look, Box::new(e)));
+            }
+        }
+        _ => (),
+    }
+    candidates
+}
+
+/// 限量子で修飾した式の候補を追加する
+///
+/// 非貪欲な場合は貪欲にしたものを、次に修飾する式eを小さくしたものを追加する。
+/// makeは、修飾する式と貪欲かどうかから限量子の式を生成する
+fn shrink_quantifier<F>(e: &AST, greedy: bool, candidates: &mut Vec<AST>, make: F)
+where
+    F: Fn(Box<AST>, bool) -> AST,
+{
+    if !greedy {
+        candidates.push(make(Box::new(e.clone()), true));
+    }
+    for e in shrink_ast(e) {
+        candidates.push(make(Box::new(e), greedy));
+    }
+}
+
+/// 文字列inputを1文字だけ小さくした候補を返す
+///
+/// 1文字を削除するか、a以外の1文字をaにする
+fn shrink_input(input: &[char]) -> Vec<Vec<char>> {
+    let mut candidates = Vec::new();
+    for i in 0..input.len() {
+        let mut v = input.to_vec();
+        v.remove(i);
+        candidates.push(v);
+    }
+    for (i, c) in input.iter().enumerate() {
+        if *c != 'a' {
+            let mut v = input.to_vec();
+            v[i] = 'a';
+            candidates.push(v);
+        }
+    }
+    candidates
+}
+
+/// キャプチャグループの番号を、パーサと同じく開き括弧の順に振り直す
+///
+/// ncapは、それまでに現れたグループの数
+fn renumber(ast: &mut AST, ncap: &mut usize) {
+    match ast {
+        AST::Capture(n, _, e) => {
+            *ncap += 1;
+            *n = *ncap;
+            renumber(e, ncap);
+        }
+        AST::Plus(e, _)
+        | AST::Star(e, _)
+        | AST::Question(e, _)
+        | AST::Repeat(e, _, _, _)
+        | AST::Look(_, e) => renumber(e, ncap),
+        AST::Or(e1, e2) => {
+            renumber(e1, ncap);
+            renumber(e2, ncap);
+        }
+        AST::Seq(v) => v.iter_mut().for_each(|e| renumber(e, ncap)),
+        _ => (),
+    }
+}
+
+/// ランダムなASTと入力文字列を生成する
+struct AstGenerator {
+    rng: Rng,
+    ncap: usize, // 生成したキャプチャグループの数
+}
+
+impl AstGenerator {
+    /// 深さがdepth以下のASTを生成
+    ///
+    /// キャプチャグループは、パーサと同じく開き括弧の順に番号を付ける
+    fn gen(&mut self, depth: usize) -> AST {
+        if depth == 0 || self.rng.below(3) == 0 {
+            return self.gen_leaf();
+        }
+
+        let greedy = self.rng.below(3) != 0;
+        match self.rng.below(8) {
+            0 => AST::Plus(Box::new(self.gen(depth - 1)), greedy),
+            1 => AST::Star(Box::new(self.gen(depth - 1)), greedy),
+            2 => AST::Question(Box::new(self.gen(depth - 1)), greedy),
+            3 => {
+                let min = self.rng.below(3);
+                let max = match self.rng.below(3) {
+                    0 => None,
+                    _ => Some(self.rng.range(min, min + 2)),
+                };
+                AST::Repeat(Box::new(self.gen(depth - 1)), min, max, greedy)
+            }
+            4 => {
+                let e1 = self.gen(depth - 1);
+                let e2 = self.gen(depth - 1);
+                AST::Or(Box::new(e1), Box::new(e2))
+            }
+            5 => {
+                let len = self.rng.range(2, 3);
+                AST::Seq((0..len).map(|_| self.gen(depth - 1)).collect())
+            }
+            6 => {
+                self.ncap += 1;
+                let n = self.ncap;
+                AST::Capture(n, None, Box::new(self.gen(depth - 1)))
+            }
+            _ => {
+                let e = self.gen(depth - 1);
+                let negative = self.rng.below(2) == 0;
+                // 後読みは、マッチする文字数に上限がある式のみとする
+                let look = match (self.rng.below(2), e.match_len()) {
+                    (0, (min, Some(max))) if negative => Look::NegativeBehind(min, max),
+                    (0, (min, Some(max))) => Look::Behind(min, max),
+                    _ if negative => Look::NegativeAhead,
+                    _ => Look::Ahead,
+                };
+                AST::Look(look, Box::new(e))
+            }
+        }
+    }
+
+    /// 子を持たないASTを生成
+    fn gen_leaf(&mut self) -> AST {
+        match self.rng.below(8) {
+            0..=3 => AST::Char(self.char()),
+            4 => {
+                let len = self.rng.range(1, 2);
+                let ranges = (0..len)
+                    .map(|_| {
+                        let (c1, c2) = (self.char(), self.char());
+                        (c1.min(c2), c1.max(c2))
+                    })
+                    .collect();
+                let negated = self.rng.below(2) == 0;
+                AST::Class(Class { ranges, negated })
+            }
+            5 => AST::Any,
+            6 => AST::AnyNewline,
+            _ => AST::Assert(ASSERTIONS[self.rng.below(ASSERTIONS.len())]),
+        }
+    }
+
+    /// MAX_INPUT_LEN文字以下の入力文字列を生成
+    fn input(&mut self) -> String {
+        let len = self.rng.range(0, MAX_INPUT_LEN);
+        (0..len).map(|_| self.char()).collect()
+    }
+
+    fn char(&mut self) -> char {
+        ALPHABET[self.rng.below(ALPHABET.len())]
+    }
+}
@@ -0,0 +1,417 @@
+//! 正規表現が表す言語の等価性と包含関係の判定
+//!
+//! 命令列から部分集合構成法で完全なDFAを構築し、Hopcroftのアルゴリズムで最小化する。
+//! 2つの最小DFAの積オートマトンを開始状態から幅優先探索し、
+//! 反例となる状態の組に到達した場合は、そこまでの経路の文字列を反例として返す。
+//! 幅優先探索のため、反例は最も短い文字列となる
+//!
+//! 正規表現の言語は、文字列全体がマッチする文字列の集合とする
+use super::{
+    codegen,
+    dfa::{self, Context, DfaError, Lookahead, StateKey},
+    evaluator,
+    regex::Regex,
+    Instruction,
+};
+use crate::helper::{safe_add, DynError};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque},
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 構築するDFAの状態数の上限
+const STATE_LIMIT: usize = 10_000;
+
+#[derive(Debug)]
+pub enum EquivError {
+    Unsupported,          // DFAで表せない命令を含む
+    TooManyStates(usize), // DFAの状態数が上限を超えた
+}
+
+impl Display for EquivError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EquivError::Unsupported => write!(
+                f,
+                "EquivError: back references, word boundaries and look-around are not supported"
+            ),
+            EquivError::TooManyStates(limit) => {
+                write!(f, "EquivError: too many DFA states: limit = {limit}")
+            }
+        }
+    }
+}
+
+impl Error for EquivError {}
+
+/// 言語の等価性や包含関係の判定結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Holds,                  // 成り立つ
+    Counterexample(String), // 成り立たない。反例となる文字列を持つ
+}
+
+impl Comparison {
+    /// 成り立つ場合にtrueを返す
+    pub fn holds(&self) -> bool {
+        matches!(self, Comparison::Holds)
+    }
+
+    /// 成り立たない場合は、反例となる文字列を返す
+    pub fn counterexample(&self) -> Option<&str> {
+        match self {
+            Comparison::Holds => None,
+            Comparison::Counterexample(text) => Some(text),
+        }
+    }
+}
+
+impl Regex {
+    /// selfとotherが同じ言語を表すかを判定
+    ///
+    /// 言語は、文字列全体がマッチする文字列の集合とする。
+    /// 等価でない場合は、一方の正規表現のみが全体にマッチする最も短い文字列を反例とする。
+    /// 後方参照、\b、\B、先読みと後読みを含む場合はErrを返す
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let a = Regex::new("(a|b)*").unwrap();
+    /// assert!(a.equivalent(&Regex::new("(a*b*)*").unwrap()).unwrap().holds());
+    ///
+    /// let c = a.equivalent(&Regex::new("a*b*").unwrap()).unwrap();
+    /// assert_eq!(c.counterexample(), Some("ba"));
+    /// ```
+    pub fn equivalent(&self, other: &Regex) -> Result<Comparison, DynError> {
+        compare(self, other, |left, right| left != right)
+    }
+
+    /// selfの言語がotherの言語を含むかを判定
+    ///
+    /// 含まない場合は、otherのみが全体にマッチする最も短い文字列を反例とする
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let a = Regex::new("[a-z]+").unwrap();
+    /// assert!(a.includes(&Regex::new("abc|xyz").unwrap()).unwrap().holds());
+    ///
+    /// let c = a.includes(&Regex::new("[a-z]*").unwrap()).unwrap();
+    /// assert_eq!(c.counterexample(), Some(""));
+    /// ```
+    pub fn includes(&self, other: &Regex) -> Result<Comparison, DynError> {
+        compare(self, other, |left, right| !left && right)
+    }
+}
+
+/// 2つの正規表現の最小DFAの積オートマトンを幅優先探索し、反例を求める
+///
+/// is_counterexampleは、左右のDFAがそれぞれ受理するかを受け取り、反例となる場合にtrueを返す
+fn compare<F>(left: &Regex, right: &Regex, is_counterexample: F) -> Result<Comparison, DynError>
+where
+    F: Fn(bool, bool) -> bool,
+{
+    let left = codegen::get_code(&left.ast)?;
+    let right = codegen::get_code(&right.ast)?;
+    let alphabet = alphabet(&[&left, &right]);
+    let left = Dfa::new(&left, &alphabet)?.minimize();
+    let right = Dfa::new(&right, &alphabet)?.minimize();
+
+    // 到達した状態の組から、その直前の状態の組と文字の番号への対応
+    let mut parents = HashMap::new();
+    let mut queue = VecDeque::new();
+    parents.insert((0, 0), None);
+    queue.push_back((0, 0));
+
+    while let Some((l, r)) = queue.pop_front() {
+        if is_counterexample(left.accept[l], right.accept[r]) {
+            // 直前の状態の組を開始状態まで辿り、経路の文字列を求める
+            let mut text = Vec::new();
+            let mut pair = (l, r);
+            while let Some((prev, a)) = parents[&pair] {
+                text.push(alphabet[a]);
+                pair = prev;
+            }
+            return Ok(Comparison::Counterexample(text.iter().rev().collect()));
+        }
+
+        for a in 0..alphabet.len() {
+            let next = (left.next[l][a], right.next[r][a]);
+            if let Entry::Vacant(e) = parents.entry(next) {
+                e.insert(Some(((l, r), a)));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    Ok(Comparison::Holds)
+}
+
+/// 命令列codesの文字を消費する命令が区別しない文字をまとめ、それぞれを代表する文字を返す
+///
+/// どの命令にマッチするかが同じ文字同士は、DFAでの遷移も同じとなるため1文字で代表させる。
+/// 改行はアサーションの判定に用いるため、常に他の文字と区別する
+fn alphabet(codes: &[&[Instruction]]) -> Vec<char> {
+    let insts = codes
+        .iter()
+        .flat_map(|code| code.iter())
+        .filter(|inst| {
+            matches!(
+                inst,
+                Instruction::Char(_)
+                    | Instruction::Class(_)
+                    | Instruction::Any
+                    | Instruction::AnyNewline
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // 各命令にマッチする文字の範囲の境界で、文字全体を区間に分ける。.の境界は改行の前後となる
+    let mut bounds = vec![0, '\n' as u32, '\n' as u32 + 1];
+    for inst in insts.iter() {
+        let ranges = match inst {
+            Instruction::Char(c) => vec![(*c, *c)],
+            Instruction::Class(class) => class.to_ranges(),
+            _ => continue,
+        };
+        for (start, end) in ranges {
+            bounds.push(start as u32);
+            bounds.push(end as u32 + 1);
+        }
+    }
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    // マッチする命令の組が同じ区間を、同じ文字の種類とする
+    let mut kinds = HashMap::new();
+    let mut intervals = Vec::new(); // (区間の先頭, 区間の末尾, 文字の種類)
+    for (i, start) in bounds.iter().enumerate() {
+        let end = bounds.get(i + 1).map_or(char::MAX as u32, |b| b - 1);
+        // サロゲートのみの区間と、char::MAXより後ろの区間は文字を含まない
+        let c = match (*start..=end).find_map(char::from_u32) {
+            Some(c) => c,
+            None => continue,
+        };
+        let key = insts
+            .iter()
+            .map(|inst| evaluator::is_match_char(inst, c))
+            .chain([c == '\n'])
+            .collect::<Vec<_>>();
+        let n = kinds.len();
+        let kind = *kinds.entry(key).or_insert(n);
+        intervals.push((*start, end, kind));
+    }
+
+    // 反例を読みやすくするため、代表の文字は英数字、記号、制御文字以外、制御文字の順に選ぶ
+    let mut reps = vec![None; kinds.len()];
+    let preferred = ('a'..='z')
+        .chain('A'..='Z')
+        .chain('0'..='9')
+        .chain('!'..='~')
+        .chain([' ']);
+    for c in preferred {
+        let c32 = c as u32;
+        if let Some((_, _, kind)) = intervals.iter().find(|(s, e, _)| *s <= c32 && c32 <= *e) {
+            reps[*kind].get_or_insert(c);
+        }
+    }
+    for (start, end, kind) in intervals.iter() {
+        let mut chars = (*start..=*end).filter_map(char::from_u32).take(256);
+        if let Some(c) = chars.find(|c| !c.is_control()) {
+            reps[*kind].get_or_insert(c);
+        }
+    }
+    for (start, end, kind) in intervals.iter() {
+        if let Some(c) = (*start..=*end).find_map(char::from_u32) {
+            reps[*kind].get_or_insert(c);
+        }
+    }
+
+    // どの種類も、文字を含む区間から作るため代表の文字がある
+    reps.into_iter().flatten().collect()
+}
+
+/// 完全なDFA。状態0を開始状態とする
+struct Dfa {
+    next: Vec<Vec<usize>>, // next[s][a]は、状態sからalphabetのa番目の文字で遷移する先の状態
+    accept: Vec<bool>,     // 文字列の末尾で受理するか
+}
+
+impl Dfa {
+    /// 命令列instから、alphabetの各文字で遷移するDFAを部分集合構成法で構築
+    ///
+    /// 状態は遅延DFAと同様に、NFAの状態集合、Match命令に到達しているか、位置の情報の組とする。
+    /// ただし文字列全体のマッチを判定するため、途中でMatch命令に到達した状態を受理し続けることはしない
+    fn new(inst: &[Instruction], alphabet: &[char]) -> Result<Dfa, DynError> {
+        if !inst.iter().all(dfa::is_supported) {
+            return Err(Box::new(EquivError::Unsupported));
+        }
+
+        let ctx = Context {
+            at_begin: true,
+            after_nl: false,
+        };
+        let (pcs, is_match) = dfa::closure(inst, &[0], ctx, Lookahead::Unknown)?;
+        let start: StateKey = (pcs, is_match, ctx);
+
+        let mut keys = vec![start.clone()]; // 状態の番号から、状態集合などの組への対応
+        let mut ids = HashMap::from([(start, 0)]);
+        let mut next = Vec::new();
+        let mut accept = Vec::new();
+
+        // 生成した状態を順に取り出し、遷移先の状態を生成する
+        let mut s = 0;
+        while s < keys.len() {
+            let (pcs, is_match, ctx) = keys[s].clone();
+
+            // 判定待ちの$を、文字列の末尾として評価
+            let ends = dfa::pending(inst, &pcs, Lookahead::End);
+            let (_, is_match_at_end) = dfa::closure(inst, &ends, ctx, Lookahead::End)?;
+            accept.push(is_match || is_match_at_end);
+
+            let mut row = Vec::with_capacity(alphabet.len());
+            for c in alphabet.iter() {
+                let key = step(inst, &pcs, ctx, *c)?;
+                let t = match ids.get(&key) {
+                    Some(t) => *t,
+                    None => {
+                        if keys.len() >= STATE_LIMIT {
+                            return Err(Box::new(EquivError::TooManyStates(STATE_LIMIT)));
+                        }
+                        ids.insert(key.clone(), keys.len());
+                        keys.push(key);
+                        keys.len() - 1
+                    }
+                };
+                row.push(t);
+            }
+            next.push(row);
+            s += 1;
+        }
+
+        Ok(Dfa { next, accept })
+    }
+
+    /// Hopcroftのアルゴリズムで、同じ言語を受理する状態数が最小のDFAを求める
+    ///
+    /// 受理状態とそれ以外の状態の2つの区画から始め、ある区画へ同じ文字で遷移する状態と
+    /// 遷移しない状態が同じ区画にある場合は、その区画を分割する。
+    /// 分割した区画のうち、小さい方のみを以降の分割に用いる
+    fn minimize(&self) -> Dfa {
+        let n = self.accept.len();
+        let k = self.next.first().map_or(0, |row| row.len());
+
+        // 文字ごとの逆向きの遷移
+        let mut prev = vec![vec![Vec::new(); n]; k];
+        for (s, row) in self.next.iter().enumerate() {
+            for (a, t) in row.iter().enumerate() {
+                prev[a][*t].push(s);
+            }
+        }
+
+        let mut block = vec![0; n]; // 各状態の属する区画
+        let mut blocks: Vec<Vec<usize>> = Vec::new();
+        let (accepts, rejects): (Vec<usize>, Vec<usize>) = (0..n).partition(|s| self.accept[*s]);
+        for part in [accepts, rejects] {
+            if !part.is_empty() {
+                part.iter().for_each(|s| block[*s] = blocks.len());
+                blocks.push(part);
+            }
+        }
+
+        let mut waiting = (0..blocks.len()).collect::<Vec<_>>();
+        let mut is_waiting = vec![true; blocks.len()];
+        let mut mark = vec![false; n];
+        while let Some(b) = waiting.pop() {
+            is_waiting[b] = false;
+            let splitter = blocks[b].clone();
+            for inv in prev.iter() {
+                // 区画splitterへ遷移する状態を、属する区画ごとに集める
+                let mut hits: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+                for t in splitter.iter() {
+                    for s in inv[*t].iter() {
+                        hits.entry(block[*s]).or_default().push(*s);
+                    }
+                }
+
+                for (y, xs) in hits {
+                    if xs.len() == blocks[y].len() {
+                        continue;
+                    }
+
+                    // 区画yを、splitterへ遷移する状態と、それ以外の状態に分割
+                    xs.iter().for_each(|s| mark[*s] = true);
+                    let (inside, outside): (Vec<usize>, Vec<usize>) =
+                        blocks[y].iter().partition(|s| mark[**s]);
+                    xs.iter().for_each(|s| mark[*s] = false);
+
+                    let z = blocks.len();
+                    outside.iter().for_each(|s| block[*s] = z);
+                    blocks[y] = inside;
+                    blocks.push(outside);
+                    is_waiting.push(false);
+
+                    let added = if is_waiting[y] || blocks[z].len() < blocks[y].len() {
+                        z
+                    } else {
+                        y
+                    };
+                    waiting.push(added);
+                    is_waiting[added] = true;
+                }
+            }
+        }
+
+        // 開始状態の区画が0番となるよう、状態の順に区画へ番号を付け直す
+        let mut ids = vec![None; blocks.len()];
+        let mut reps = Vec::new(); // 各区画を代表する状態
+        for s in 0..n {
+            if ids[block[s]].is_none() {
+                ids[block[s]] = Some(reps.len());
+                reps.push(s);
+            }
+        }
+        let id = |s: usize| ids[block[s]].unwrap();
+
+        Dfa {
+            next: reps
+                .iter()
+                .map(|s| self.next[*s].iter().map(|t| id(*t)).collect())
+                .collect(),
+            accept: reps.iter().map(|s| self.accept[*s]).collect(),
+        }
+    }
+}
+
+/// NFAの状態集合pcsから文字cで遷移した先の、状態集合、Match命令に到達したか、位置の情報の組を返す
+///
+/// ctxはpcsを求めた位置の情報
+fn step(inst: &[Instruction], pcs: &[usize], ctx: Context, c: char) -> Result<StateKey, DynError> {
+    // 次の文字が改行の場合は、判定待ちの(?m)$が成り立つため、その先の命令も加える
+    let mut pcs = pcs.to_vec();
+    if c == '\n' {
+        let ends = dfa::pending(inst, &pcs, Lookahead::Newline);
+        let (extra, _) = dfa::closure(inst, &ends, ctx, Lookahead::Newline)?;
+        pcs.extend(extra);
+    }
+
+    // cにマッチする命令の次の命令から、遷移先の状態集合を求める
+    let mut roots = Vec::new();
+    for pc in pcs {
+        if evaluator::is_match_char(&inst[pc], c) {
+            let mut next = pc;
+            safe_add(&mut next, &1, || Box::new(DfaError::PCOverFlow))?;
+            roots.push(next);
+        }
+    }
+
+    let ctx = Context {
+        at_begin: false,
+        after_nl: c == '\n',
+    };
+    let (pcs, is_match) = dfa::closure(inst, &roots, ctx, Lookahead::Unknown)?;
+    Ok((pcs, is_match, ctx))
+}
@@ -1,7 +1,11 @@
-use super::Instruction;
+use super::{
+    parser::{case_fold_eq, Assertion, Look},
+    text::Text,
+    Instruction,
+};
 use crate::helper::{safe_add, DynError};
 use std::{
-    // collections::VecDeque, 幅優先探索の際に使用する
+    collections::VecDeque,
     error::Error,
     fmt::{self, Display},
 };
@@ -11,68 +15,686 @@ pub enum EvalError {
     PCOverFlow,
     SPOverFlow,
     InvalidPC,
-    InvalidContext,
+    StepLimitExceeded(usize),
+    DepthLimitExceeded(usize),
+    BackRefNotSupported,
 }
 
 impl Display for EvalError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "EvaluatorError: {:?}", self)
+        match self {
+            EvalError::StepLimitExceeded(limit) => {
+                write!(f, "EvaluatorError: step limit exceeded: limit = {limit}")
+            }
+            EvalError::DepthLimitExceeded(limit) => {
+                write!(f, "EvaluatorError: depth limit exceeded: limit = {limit}")
+            }
+            EvalError::BackRefNotSupported => write!(
+                f,
+                "EvaluatorError: back references are supported only by the depth-first evaluator"
+            ),
+            _ => write!(f, "EvaluatorError: {:?}", self),
+        }
     }
 }
 
 impl Error for EvalError {}
 
-/// 深さ優先探索で再帰的にマッチングを行う関数
-fn eval_depth(
+/// 評価時の設定
+///
+/// 深さ優先探索は、(a*)*bのような正規表現に対して指数時間かかるため、
+/// 実行する命令数とバックトラックの深さに上限を設けられるようにする
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EvalConfig {
+    pub step_limit: Option<usize>,  // 深さ優先探索で実行する命令数の上限
+    pub depth_limit: Option<usize>, // 深さ優先探索でバックトラックに備えて保持する分岐の数の上限
+    pub memoize: bool, // trueの場合は、深さ優先探索で訪れた(pc, sp)を記録し、再訪しない
+}
+
+/// 文字を消費する命令（Char, Class, Any）が文字cにマッチするかを判定
+pub(super) fn is_match_char(inst: &Instruction, c: char) -> bool {
+    match inst {
+        Instruction::Char(ic) => *ic == c,
+        Instruction::Class(class) => class.is_match(c),
+        Instruction::Any => c != '\n',
+        Instruction::AnyNewline => true,
+        _ => false,
+    }
+}
+
+/// \bと\Bで単語を構成する文字かを判定。\wと同様に、ASCIIの英数字とアンダースコアとする
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// 文字列lineの位置spでアサーションが成り立つかを判定
+fn is_satisfied<T: Text + ?Sized>(assertion: Assertion, line: &T, sp: usize) -> bool {
+    // 位置spの前後の文字が、単語を構成する文字かどうかが異なるか
+    let is_boundary = || {
+        line.char_before(sp).is_some_and(is_word_char)
+            != line.char_at(sp).is_some_and(|(c, _)| is_word_char(c))
+    };
+
+    match assertion {
+        Assertion::Begin => sp == 0,
+        Assertion::End => sp == line.len(),
+        Assertion::LineBegin => sp == 0 || line.char_before(sp) == Some('\n'),
+        Assertion::LineEnd => sp == line.len() || matches!(line.char_at(sp), Some(('\n', _))),
+        Assertion::WordBoundary => is_boundary(),
+        Assertion::NotWordBoundary => !is_boundary(),
+    }
+}
+
+/// pcのLook命令が、文字列lineの位置spで成り立つかを判定
+///
+/// 部分プログラムはpc + 1から始まり、Match命令で終わる。
+/// 先読みはspから部分プログラムを評価する。
+/// 後読みはspから最小文字数から最大文字数だけ前の各位置から評価し、ちょうどspで終わるマッチを探す。
+/// 部分プログラムは、is_depthに応じて深さ優先探索か幅優先探索で評価する
+///
+/// 成り立つ場合は、スロットを返す。肯定の場合は、部分プログラムのSave命令で記録した位置を反映したものとなる。
+/// 成り立たない場合はNoneを返す
+#[allow(clippy::too_many_arguments)]
+fn eval_look<T: Text + ?Sized>(
     inst: &[Instruction],
-    line: &[char],
-    mut pc: usize,
+    line: &T,
+    pc: usize,
+    look: Look,
+    sp: usize,
+    slots: &[Option<usize>],
+    is_depth: bool,
+    config: &EvalConfig,
+) -> Result<Option<Vec<Option<usize>>>, DynError> {
+    let mut start = pc;
+    safe_add(&mut start, &1, || Box::new(EvalError::PCOverFlow))?;
+
+    // 部分プログラムの評価を開始する位置と、マッチが終わるべき位置
+    let (sps, end) = match look {
+        Look::Ahead | Look::NegativeAhead => (vec![sp], None),
+        Look::Behind(min, max) | Look::NegativeBehind(min, max) => {
+            let mut sps = Vec::new();
+            let mut p = sp;
+            for n in 0..=max {
+                if n >= min {
+                    sps.push(p);
+                }
+                if p == 0 {
+                    break;
+                }
+                p = line.prev_pos(p);
+            }
+            (sps, Some(sp))
+        }
+    };
+
+    let mut matched = None;
+    for p in sps {
+        matched = if is_depth {
+            let mut sub = slots.to_vec();
+            if eval_depth(inst, line, start, p, end, &mut sub, config)? {
+                Some(sub)
+            } else {
+                None
+            }
+        } else {
+            eval_width(inst, line, start, p, end, slots.to_vec())?
+        };
+        if matched.is_some() {
+            break;
+        }
+    }
+
+    if look.is_negative() {
+        Ok(match matched {
+            Some(_) => None,
+            None => Some(slots.to_vec()),
+        })
+    } else {
+        Ok(matched)
+    }
+}
+
+/// 後方参照の評価に必要なスロットの数を返す。後方参照がない場合は0を返す
+pub(super) fn backref_slots(inst: &[Instruction]) -> usize {
+    inst.iter()
+        .filter_map(|i| match i {
+            Instruction::BackRef(n, _) => Some(n * 2 + 2),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// 位置spから、n番目のグループにマッチした文字列と同じ文字列が続くかを判定
+///
+/// 続く場合は、その直後の位置を返す。
+/// グループがマッチに関与していない場合や、グループの評価中の場合は失敗とする
+fn match_backref<T: Text + ?Sized>(
+    line: &T,
+    slots: &[Option<usize>],
+    n: usize,
+    case_insensitive: bool,
     mut sp: usize,
-) -> Result<bool, DynError> {
-    loop {
-        let next = if let Some(i) = inst.get(pc) {
-            i
+) -> Option<usize> {
+    let (mut p, end) = match (slots.get(n * 2), slots.get(n * 2 + 1)) {
+        (Some(Some(start)), Some(Some(end))) => (*start, *end),
+        _ => return None,
+    };
+
+    while p < end {
+        let (c1, len1) = line.char_at(p)?;
+        let (c2, len2) = line.char_at(sp)?;
+        let is_same = if case_insensitive {
+            case_fold_eq(c1, c2)
         } else {
-            return Err(Box::new(EvalError::InvalidPC));
+            c1 == c2
+        };
+        if !is_same {
+            return None;
+        }
+        p += len1;
+        sp += len2;
+    }
+    Some(sp)
+}
+
+/// Save命令で、スロットnに文字位置spを記録する
+///
+/// 記録前の値を返す。スロットが用意されていない場合は何もしない
+fn save(slots: &mut [Option<usize>], n: usize, sp: usize) -> Option<usize> {
+    if let Some(slot) = slots.get_mut(n) {
+        slot.replace(sp)
+    } else {
+        None
+    }
+}
+
+/// 深さ優先探索でバックトラックした際に行う処理
+enum Backtrack {
+    Resume(usize, usize, usize), // (pc, sp)から評価を再開。3つ目はその時点のsplitsの長さ
+    Restore(usize, Option<usize>), // スロットnを元の値に戻す
+}
+
+/// 深さ優先探索で訪れた(pc, sp)の集合
+///
+/// 正規表現のマッチは(pc, sp)のみで決まるため、一度訪れて失敗した(pc, sp)は再び訪れても失敗する。
+/// そのため再訪を除外することで、命令数 × 文字列長に比例する時間で評価できる
+struct Visited {
+    bits: Vec<u64>,
+    width: usize, // 評価開始位置から文字列末尾までの位置の数
+    start: usize, // 評価開始位置
+}
+
+impl Visited {
+    fn new<T: Text + ?Sized>(inst: &[Instruction], line: &T, start: usize) -> Self {
+        let width = line.len().saturating_sub(start) + 1;
+        let len = inst.len().saturating_mul(width).div_ceil(64);
+        Visited {
+            bits: vec![0; len],
+            width,
+            start,
+        }
+    }
+
+    /// (pc, sp)を記録し、既に訪れていた場合はtrueを返す
+    fn insert(&mut self, pc: usize, sp: usize) -> bool {
+        let i = pc * self.width + (sp - self.start);
+        let (word, bit) = (i / 64, 1 << (i % 64));
+        let old = self.bits[word] & bit != 0;
+        self.bits[word] |= bit;
+        old
+    }
+}
+
+/// 深さ優先探索でマッチングを行う関数
+///
+/// Splitではaddr1側を先に評価し、失敗した場合にaddr2側を評価する。
+/// 再帰するとスタックオーバーフローするため、バックトラック先はstackに保持する。
+/// slotsにはSave命令で記録した文字位置が格納され、バックトラック時には元の値に戻す。
+/// endを指定した場合は、ちょうどendで終わるマッチのみを受理する（後読みの部分プログラム用）
+///
+/// (a*)*のように空文字列にマッチする式の繰り返しで、文字を消費せずに同じ(pc, sp)のSplitに
+/// 戻った場合は、その経路を失敗とする。そうしないと同じ繰り返しを無限に辿り、停止しない
+fn eval_depth<T: Text + ?Sized>(
+    inst: &[Instruction],
+    line: &T,
+    pc: usize,
+    sp: usize,
+    end: Option<usize>,
+    slots: &mut [Option<usize>],
+    config: &EvalConfig,
+) -> Result<bool, DynError> {
+    // 後方参照がある場合は、マッチが(pc, sp)のみでは決まらないためメモ化しない
+    let mut visited = if config.memoize && backref_slots(inst) == 0 {
+        Some(Visited::new(inst, line, sp))
+    } else {
+        None
+    };
+    let mut stack = vec![Backtrack::Resume(pc, sp, 0)];
+    let mut splits = Vec::new(); // 現在の経路で辿ったSplitの(pc, sp)。spは経路上で減らない
+    let mut steps: usize = 0;
+
+    while let Some(backtrack) = stack.pop() {
+        let (mut pc, mut sp) = match backtrack {
+            Backtrack::Resume(pc, sp, len) => {
+                splits.truncate(len);
+                (pc, sp)
+            }
+            Backtrack::Restore(n, old) => {
+                if let Some(slot) = slots.get_mut(n) {
+                    *slot = old;
+                }
+                continue;
+            }
         };
 
-        match next {
-            Instruction::Char(c) => {
-                if let Some(sp_c) = line.get(sp) {
-                    if c == sp_c {
+        loop {
+            steps = steps.saturating_add(1);
+            if let Some(limit) = config.step_limit {
+                if steps > limit {
+                    return Err(Box::new(EvalError::StepLimitExceeded(limit)));
+                }
+            }
+
+            let next = if let Some(i) = inst.get(pc) {
+                i
+            } else {
+                return Err(Box::new(EvalError::InvalidPC));
+            };
+
+            if let Some(visited) = &mut visited {
+                if visited.insert(pc, sp) {
+                    break;
+                }
+            }
+
+            match next {
+                Instruction::Char(_)
+                | Instruction::Class(_)
+                | Instruction::Any
+                | Instruction::AnyNewline => match line.char_at(sp) {
+                    Some((c, len)) if is_match_char(next, c) => {
+                        safe_add(&mut pc, &1, || Box::new(EvalError::PCOverFlow))?;
+                        safe_add(&mut sp, &len, || Box::new(EvalError::SPOverFlow))?;
+                    }
+                    _ => break,
+                },
+                Instruction::Match(_) => {
+                    if end.is_none_or(|end| end == sp) {
+                        return Ok(true);
+                    }
+                    break;
+                }
+                Instruction::BackRef(n, case_insensitive) => {
+                    match match_backref(line, slots, *n, *case_insensitive, sp) {
+                        Some(next_sp) => {
+                            safe_add(&mut pc, &1, || Box::new(EvalError::PCOverFlow))?;
+                            sp = next_sp;
+                        }
+                        None => break,
+                    }
+                }
+                Instruction::Assert(assertion) => {
+                    if is_satisfied(*assertion, line, sp) {
                         safe_add(&mut pc, &1, || Box::new(EvalError::PCOverFlow))?;
-                        safe_add(&mut sp, &1, || Box::new(EvalError::SPOverFlow))?;
                     } else {
-                        return Ok(false);
+                        break;
+                    }
+                }
+                Instruction::Save(n) => {
+                    // 後続のマッチに失敗した場合に元に戻せるよう、元の値を保存しておく
+                    let old = save(slots, *n, sp);
+                    stack.push(Backtrack::Restore(*n, old));
+                    safe_add(&mut pc, &1, || Box::new(EvalError::PCOverFlow))?;
+                }
+                Instruction::Look(look, addr) => {
+                    match eval_look(inst, line, pc, *look, sp, slots, true, config)? {
+                        Some(sub) => {
+                            // 部分プログラムで記録した位置を反映し、バックトラック時には元に戻す
+                            for (n, s) in sub.into_iter().enumerate() {
+                                if slots[n] != s {
+                                    stack.push(Backtrack::Restore(n, slots[n]));
+                                    slots[n] = s;
+                                }
+                            }
+                            pc = *addr;
+                        }
+                        None => break,
                     }
-                } else {
-                    return Ok(false);
                 }
+                Instruction::Jump(addr) => {
+                    pc = *addr;
+                }
+                Instruction::Split(addr1, addr2) => {
+                    // 文字を消費せずに同じSplitに戻った場合は、空の繰り返しのため失敗とする
+                    let is_loop = splits
+                        .iter()
+                        .rev()
+                        .take_while(|(_, s)| *s == sp)
+                        .any(|(p, _)| *p == pc);
+                    if is_loop {
+                        break;
+                    }
+                    splits.push((pc, sp));
+
+                    // addr1側の評価に失敗した場合に、addr2側から再開する
+                    stack.push(Backtrack::Resume(*addr2, sp, splits.len()));
+                    pc = *addr1;
+                }
+            }
+
+            if let Some(limit) = config.depth_limit {
+                if stack.len() > limit {
+                    return Err(Box::new(EvalError::DepthLimitExceeded(limit)));
+                }
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// 幅優先探索で実行するスレッド
+///
+/// 実行中のpcと、そのスレッドがSave命令で記録した文字位置を持つ
+pub(super) type Thread = (usize, Vec<Option<usize>>);
+
+/// スレッドリストにスレッドを追加する関数
+///
+/// Jump, Split, Save命令は文字を消費しないため、ここで辿りきってしまい、
+/// 文字を消費する命令とMatch命令のスレッドのみをリストに追加する。
+/// Assert命令とLook命令もここで判定し、成り立つ場合のみ辿る。
+/// Splitの優先順位を保つため、addr1側から先に辿る。
+/// 同じsp上で既に訪れたpcはvisitedで除外するため、リストの長さは命令数を超えない
+pub(super) fn add_thread<T: Text + ?Sized>(
+    inst: &[Instruction],
+    line: &T,
+    sp: usize,
+    list: &mut VecDeque<Thread>,
+    visited: &mut [bool],
+    thread: Thread,
+) -> Result<(), DynError> {
+    let mut stack = vec![thread];
+    while let Some((pc, mut slots)) = stack.pop() {
+        if let Some(v) = visited.get_mut(pc) {
+            if *v {
+                continue;
             }
-            Instruction::Match => {
-                return Ok(true);
+            *v = true;
+        } else {
+            return Err(Box::new(EvalError::InvalidPC));
+        }
+
+        match &inst[pc] {
+            Instruction::Char(_)
+            | Instruction::Class(_)
+            | Instruction::Any
+            | Instruction::AnyNewline
+            | Instruction::Match(_) => list.push_back((pc, slots)),
+            Instruction::Assert(assertion) => {
+                if is_satisfied(*assertion, line, sp) {
+                    let mut next = pc;
+                    safe_add(&mut next, &1, || Box::new(EvalError::PCOverFlow))?;
+                    stack.push((next, slots));
+                }
             }
-            Instruction::Jump(addr) => {
-                pc = *addr;
+            Instruction::Save(n) => {
+                save(&mut slots, *n, sp);
+                let mut next = pc;
+                safe_add(&mut next, &1, || Box::new(EvalError::PCOverFlow))?;
+                stack.push((next, slots));
             }
+            Instruction::Jump(addr) => stack.push((*addr, slots)),
             Instruction::Split(addr1, addr2) => {
-                if eval_depth(inst, line, *addr1, sp)? || eval_depth(inst, line, *addr2, sp)? {
-                    return Ok(true);
-                } else {
-                    return Ok(false);
+                stack.push((*addr2, slots.clone()));
+                stack.push((*addr1, slots));
+            }
+            Instruction::Look(look, addr) => {
+                let config = EvalConfig::default();
+                if let Some(slots) = eval_look(inst, line, pc, *look, sp, &slots, false, &config)? {
+                    stack.push((*addr, slots));
                 }
             }
+            Instruction::BackRef(_, _) => return Err(Box::new(EvalError::BackRefNotSupported)),
         }
     }
+    Ok(())
+}
+
+/// 幅優先探索でマッチングを行う関数
+///
+/// 入力文字列を1文字ずつ読み進めながら、その位置で生きている
+/// すべてのスレッド（pc）を同時に1ステップずつ実行する（Pike VM）。
+/// 同じpcのスレッドは一つにまとめられるため、計算量は
+/// O(命令数 × 文字列長)となり、深さ優先探索のような指数爆発は起きない
+///
+/// スレッドリストは優先度の高い順に並んでいるため、
+/// Match命令に到達したスレッドより優先度の低いスレッドは破棄し、
+/// 優先度の高いスレッドのみ実行を続ける。
+/// これにより、深さ優先探索と同じマッチ結果を得られる
+///
+/// pcから評価を開始し、slotsを初期のスロットとする。
+/// endを指定した場合は、ちょうどendで終わるマッチのみを受理する（後読みの部分プログラム用）
+fn eval_width<T: Text + ?Sized>(
+    inst: &[Instruction],
+    line: &T,
+    pc: usize,
+    sp: usize,
+    end: Option<usize>,
+    slots: Vec<Option<usize>>,
+) -> Result<Option<Vec<Option<usize>>>, DynError> {
+    // 後方参照は、スレッドごとに異なる長さの文字を消費するため評価できない
+    if backref_slots(inst) > 0 {
+        return Err(Box::new(EvalError::BackRefNotSupported));
+    }
+
+    let mut clist = VecDeque::new(); // 現在の文字位置で実行するスレッド
+    let mut nlist = VecDeque::new(); // 次の文字位置で実行するスレッド
+    let mut visited = vec![false; inst.len()];
+    let mut matched = None;
+
+    let nslots = slots.len();
+    add_thread(inst, line, sp, &mut clist, &mut visited, (pc, slots))?;
+
+    let mut sp = sp;
+    // endより後ろで終わるマッチは受理しないため、endを超えたら評価を打ち切る
+    while !clist.is_empty() && end.is_none_or(|end| sp <= end) {
+        if let Some(slots) = step_width(inst, line, sp, end, &mut clist, &mut nlist, &mut visited)?
+        {
+            // スロットがない場合は、マッチの有無のみが必要なため直ちに返す
+            if nslots == 0 {
+                return Ok(Some(slots));
+            }
+            matched = Some(slots);
+        }
+
+        std::mem::swap(&mut clist, &mut nlist);
+        sp = line.next_pos(sp);
+    }
+
+    Ok(matched)
+}
+
+/// 幅優先探索で、位置spの文字についてclistのスレッドを1文字分評価する
+///
+/// 文字にマッチしたスレッドは、次の文字位置で実行するスレッドとしてnlistに追加する。
+/// Match命令に到達した場合は、優先度の低いスレッドを破棄し、そのスレッドのスロットを返す
+pub(super) fn step_width<T: Text + ?Sized>(
+    inst: &[Instruction],
+    line: &T,
+    sp: usize,
+    end: Option<usize>,
+    clist: &mut VecDeque<Thread>,
+    nlist: &mut VecDeque<Thread>,
+    visited: &mut [bool],
+) -> Result<Option<Vec<Option<usize>>>, DynError> {
+    visited.iter_mut().for_each(|v| *v = false);
+
+    // 現在の位置の文字と、次の文字の位置
+    let c = line.char_at(sp);
+    let next_sp = line.next_pos(sp);
+
+    while let Some((pc, slots)) = clist.pop_front() {
+        let is_match = match &inst[pc] {
+            Instruction::Match(_) => {
+                if end.is_some_and(|end| end != sp) {
+                    continue;
+                }
+                // 優先度の低いスレッドを破棄
+                clist.clear();
+                return Ok(Some(slots));
+            }
+            // add_threadはJump, Split, Assert, Save, Lookをリストに追加しない
+            Instruction::Jump(_)
+            | Instruction::Split(_, _)
+            | Instruction::Assert(_)
+            | Instruction::Save(_)
+            | Instruction::Look(_, _) => {
+                return Err(Box::new(EvalError::InvalidPC));
+            }
+            i => c.is_some_and(|(c, _)| is_match_char(i, c)),
+        };
+
+        // 文字にマッチしたスレッドのみ、次の文字位置へ進める
+        if is_match {
+            let mut next = pc;
+            safe_add(&mut next, &1, || Box::new(EvalError::PCOverFlow))?;
+            add_thread(inst, line, next_sp, nlist, visited, (next, slots))?;
+        }
+    }
+    Ok(None)
+}
+
+/// 複数の正規表現のコードを幅優先探索で評価し、マッチした正規表現を求める関数
+///
+/// instはcodegen::get_set_codeで生成した命令列で、npatternsはその正規表現の数。
+/// Match(i)命令に到達したスレッドがあれば、i番目の正規表現がマッチしたとする。
+/// eval_widthと異なり、Match命令に到達しても他のスレッドを破棄せずに文字列の末尾まで評価を続けるため、
+/// 文字列の走査は1度で済む。すべての正規表現がマッチした時点で評価を打ち切る
+///
+/// i番目の要素が、i番目の正規表現がマッチしたかを表すベクタを返す
+pub fn eval_set<T: Text + ?Sized>(
+    inst: &[Instruction],
+    line: &T,
+    npatterns: usize,
+) -> Result<Vec<bool>, DynError> {
+    let mut matched = vec![false; npatterns];
+    if npatterns == 0 {
+        return Ok(matched);
+    }
+
+    let mut clist = VecDeque::new(); // 現在の文字位置で実行するスレッド
+    let mut nlist = VecDeque::new(); // 次の文字位置で実行するスレッド
+    let mut visited = vec![false; inst.len()];
+    let mut count = 0; // マッチした正規表現の数
+
+    add_thread(inst, line, 0, &mut clist, &mut visited, (0, Vec::new()))?;
+
+    let mut sp = 0;
+    while !clist.is_empty() {
+        visited.iter_mut().for_each(|v| *v = false);
+
+        // 現在の位置の文字と、次の文字の位置
+        let c = line.char_at(sp);
+        let next_sp = line.next_pos(sp);
+
+        while let Some((pc, slots)) = clist.pop_front() {
+            let is_match = match &inst[pc] {
+                Instruction::Match(id) => {
+                    if let Some(m) = matched.get_mut(*id) {
+                        if !*m {
+                            *m = true;
+                            count += 1;
+                        }
+                    }
+                    if count == npatterns {
+                        return Ok(matched);
+                    }
+                    false
+                }
+                // add_threadはJump, Split, Assert, Save, Lookをリストに追加しない
+                Instruction::Jump(_)
+                | Instruction::Split(_, _)
+                | Instruction::Assert(_)
+                | Instruction::Save(_)
+                | Instruction::Look(_, _) => {
+                    return Err(Box::new(EvalError::InvalidPC));
+                }
+                i => c.is_some_and(|(c, _)| is_match_char(i, c)),
+            };
+
+            // 文字にマッチしたスレッドのみ、次の文字位置へ進める
+            if is_match {
+                let mut next = pc;
+                safe_add(&mut next, &1, || Box::new(EvalError::PCOverFlow))?;
+                add_thread(inst, line, next_sp, &mut nlist, &mut visited, (next, slots))?;
+            }
+        }
+
+        std::mem::swap(&mut clist, &mut nlist);
+        sp = next_sp;
+    }
+
+    Ok(matched)
+}
+
+/// 先頭からマッチさせる命令列instが、文字列line全体にマッチするかを判定
+///
+/// 後方参照と先読み・後読みも評価できるよう、深さ優先探索で評価する
+pub fn eval_full<T: Text + ?Sized>(
+    inst: &[Instruction],
+    line: &T,
+    config: &EvalConfig,
+) -> Result<bool, DynError> {
+    let mut slots = vec![None; backref_slots(inst)];
+    eval_depth(inst, line, 0, 0, Some(line.len()), &mut slots, config)
 }
 
 /// 命令列の評価を行う関数
 ///
-/// instが命令列となり、その命令列を用いて入力文字列lineにマッチさせる
+/// instが命令列となり、その命令列を用いて入力文字列lineにマッチさせる。
+/// lineはUTF-8のバイト列とする。
 /// is_depthがtrueの場合に深さ優先探索を、falseの場合に幅優先探索を行う
 ///
 /// 実行時エラーが起きた場合はErrを返す
 /// マッチ成功時はOk(true)を、失敗時はOk(false)を返す
-pub fn eval(inst: &[Instruction], line: &[char], is_depth: bool) -> Result<bool, DynError> {
-    eval_depth(inst, line, 0, 0)
+pub fn eval<T: Text + ?Sized>(
+    inst: &[Instruction],
+    line: &T,
+    is_depth: bool,
+) -> Result<bool, DynError> {
+    let config = EvalConfig::default();
+    Ok(eval_slots(inst, line, 0, 0, is_depth, &config)?.is_some())
+}
+
+/// 命令列の評価を行い、Save命令で記録した文字位置を返す関数
+///
+/// 入力文字列lineの位置spから評価を開始し、nslots個のスロットを用意して実行する。
+/// 位置はバイト単位となる。
+/// is_depthがtrueの場合に深さ優先探索を、falseの場合に幅優先探索を行う。
+/// configの上限は深さ優先探索にのみ適用し、先読みと後読みの部分プログラムの評価には個別に適用する。
+/// 後方参照は深さ優先探索でのみ評価でき、幅優先探索の場合はErrを返す
+///
+/// 実行時エラーが起きた場合はErrを返す
+/// マッチ成功時はOk(Some(スロット))を、失敗時はOk(None)を返す
+pub fn eval_slots<T: Text + ?Sized>(
+    inst: &[Instruction],
+    line: &T,
+    sp: usize,
+    nslots: usize,
+    is_depth: bool,
+    config: &EvalConfig,
+) -> Result<Option<Vec<Option<usize>>>, DynError> {
+    if is_depth {
+        // 後方参照が参照するグループの位置は、nslotsによらず記録する
+        let mut slots = vec![None; nslots.max(backref_slots(inst))];
+        if eval_depth(inst, line, 0, sp, None, &mut slots, config)? {
+            slots.truncate(nslots);
+            Ok(Some(slots))
+        } else {
+            Ok(None)
+        }
+    } else {
+        eval_width(inst, line, 0, sp, None, vec![None; nslots])
+    }
 }
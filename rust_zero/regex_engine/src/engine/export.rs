@@ -0,0 +1,417 @@
+//! ASTと命令列の、Graphviz（DOT言語）とJSONへの書き出し
+//!
+//! DOTではASTを木として、命令列をsplitとjumpを辺とする制御フローグラフとして出力する。
+//! 出力はdot -Tsvgなどで画像に変換できる
+use super::{
+    parser::{Assertion, Class, Look, AST},
+    Instruction, Regex,
+};
+use std::fmt::Write;
+
+/// 正規表現のASTと命令列をDOT言語で出力
+///
+/// ASTと命令列はそれぞれ別のクラスタとして、1つのグラフに出力する
+///
+/// ```
+/// use regex_engine::engine::{self, Regex};
+/// let re = Regex::new("a|b*").unwrap();
+/// let dot = engine::export_dot(&re);
+/// assert!(dot.starts_with("digraph regex {"));
+/// assert!(dot.contains("label=\"|\""));
+/// ```
+pub fn export_dot(regex: &Regex) -> String {
+    let mut dot = String::new();
+    dot.push_str("digraph regex {\n");
+    dot.push_str("    node [fontname=\"monospace\"];\n");
+    writeln!(dot, "    label=\"{}\";", escape_dot(regex.as_str())).unwrap();
+
+    dot.push_str("\n    subgraph cluster_ast {\n");
+    dot.push_str("        label=\"AST\";\n");
+    let mut id = 0;
+    ast_to_dot(&regex.ast, &mut id, &mut dot);
+    dot.push_str("    }\n");
+
+    dot.push_str("\n    subgraph cluster_code {\n");
+    dot.push_str("        label=\"code\";\n");
+    dot.push_str("        node [shape=box];\n");
+    code_to_dot(&regex.code, &mut dot);
+    dot.push_str("    }\n");
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// ASTのノードをDOT言語で出力し、そのノードのIDを返す
+///
+/// IDはidから順に振り、出力後のidは次に振るIDとなる
+fn ast_to_dot(ast: &AST, id: &mut usize, dot: &mut String) -> usize {
+    let node = *id;
+    *id += 1;
+
+    let label = match ast {
+        AST::Char(c) => format!("{:?}", c),
+        AST::Class(class) => class.to_string(),
+        AST::Any => ".".to_string(),
+        AST::AnyNewline => "(?s:.)".to_string(),
+        AST::Assert(assertion) => assertion.to_string(),
+        AST::Plus(_, greedy) => quantifier("+", *greedy),
+        AST::Star(_, greedy) => quantifier("*", *greedy),
+        AST::Question(_, greedy) => quantifier("?", *greedy),
+        AST::Repeat(_, min, max, greedy) => {
+            let range = match max {
+                Some(max) if max == min => format!("{{{min}}}"),
+                Some(max) => format!("{{{min},{max}}}"),
+                None => format!("{{{min},}}"),
+            };
+            quantifier(&range, *greedy)
+        }
+        AST::Or(_, _) => "|".to_string(),
+        AST::Seq(_) => "seq".to_string(),
+        AST::Capture(n, Some(name), _) => format!("group {n} <{name}>"),
+        AST::Capture(n, None, _) => format!("group {n}"),
+        AST::BackRef(n, false) => format!("\\{n}"),
+        AST::BackRef(n, true) => format!("(?i:\\{n})"),
+        AST::Look(look, _) => match look {
+            Look::Ahead => "(?=)".to_string(),
+            Look::NegativeAhead => "(?!)".to_string(),
+            Look::Behind(min, max) => format!("(?<=) {{{min},{max}}}"),
+            Look::NegativeBehind(min, max) => format!("(?<!) {{{min},{max}}}"),
+        },
+    };
+    writeln!(dot, "        ast{node} [label=\"{}\"];", escape_dot(&label)).unwrap();
+
+    let children: Vec<&AST> = match ast {
+        AST::Plus(e, _)
+        | AST::Star(e, _)
+        | AST::Question(e, _)
+        | AST::Repeat(e, _, _, _)
+        | AST::Capture(_, _, e)
+        | AST::Look(_, e) => vec![e],
+        AST::Or(e1, e2) => vec![e1, e2],
+        AST::Seq(v) => v.iter().collect(),
+        _ => vec![],
+    };
+    for child in children {
+        let child = ast_to_dot(child, id, dot);
+        writeln!(dot, "        ast{node} -> ast{child};").unwrap();
+    }
+
+    node
+}
+
+/// 限量子の表示。非貪欲な場合は?を付ける
+fn quantifier(q: &str, greedy: bool) -> String {
+    if greedy {
+        q.to_string()
+    } else {
+        format!("{q}?")
+    }
+}
+
+/// 命令列を制御フローグラフとしてDOT言語で出力
+///
+/// splitの辺には、優先して試す方に1、もう一方に2のラベルを付ける。
+/// lookから部分プログラムへの辺は破線とする
+fn code_to_dot(code: &[Instruction], dot: &mut String) {
+    for (pc, inst) in code.iter().enumerate() {
+        let label = match inst {
+            Instruction::Char(c) => format!("{pc:>04}: char {:?}", c),
+            _ => format!("{pc:>04}: {inst}"),
+        };
+        let attr = if let Instruction::Match(_) = inst {
+            ", peripheries=2"
+        } else {
+            ""
+        };
+        writeln!(
+            dot,
+            "        pc{pc} [label=\"{}\"{attr}];",
+            escape_dot(&label)
+        )
+        .unwrap();
+    }
+
+    for (pc, inst) in code.iter().enumerate() {
+        match inst {
+            Instruction::Match(_) => (),
+            Instruction::Jump(addr) => writeln!(dot, "        pc{pc} -> pc{addr};").unwrap(),
+            Instruction::Split(addr1, addr2) => {
+                writeln!(dot, "        pc{pc} -> pc{addr1} [label=\"1\"];").unwrap();
+                writeln!(dot, "        pc{pc} -> pc{addr2} [label=\"2\"];").unwrap();
+            }
+            Instruction::Look(_, addr) => {
+                writeln!(dot, "        pc{pc} -> pc{} [style=dashed];", pc + 1).unwrap();
+                writeln!(dot, "        pc{pc} -> pc{addr};").unwrap();
+            }
+            _ => {
+                if pc + 1 < code.len() {
+                    writeln!(dot, "        pc{pc} -> pc{};", pc + 1).unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// DOT言語の文字列リテラル中で用いるためにエスケープ
+///
+/// 制御文字は\nのようなエスケープシーケンスとして表示されるようにする
+fn escape_dot(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                for e in c.escape_default() {
+                    if e == '\\' {
+                        escaped.push_str("\\\\");
+                    } else {
+                        escaped.push(e);
+                    }
+                }
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 正規表現のASTと命令列をJSONで出力
+///
+/// 出力は以下の形式となる。prefixはマッチが必ず始まる文字列で、ない場合はnullとなる
+///
+/// ```text
+/// {"expr": "...", "prefix": "...", "ast": {"type": "...", ...}, "code": [{"pc": 0, "op": "...", ...}, ...]}
+/// ```
+///
+/// ```
+/// use regex_engine::engine::{self, RegexBuilder};
+/// let re = RegexBuilder::new("a+").optimize(false).build().unwrap();
+/// let json = engine::export_json(&re);
+/// assert!(json.contains(r#"{"type":"plus","greedy":true,"expr":{"type":"char","char":"a"}}"#));
+/// assert!(json.contains(r#"{"pc":0,"op":"split","targets":[3,1]}"#));
+/// ```
+pub fn export_json(regex: &Regex) -> String {
+    let mut json = String::new();
+    json.push_str("{\"expr\":");
+    json.push_str(&escape_json(regex.as_str()));
+    json.push_str(",\"prefix\":");
+    match &regex.prefix {
+        Some(prefix) => json.push_str(&escape_json(prefix)),
+        None => json.push_str("null"),
+    }
+    json.push_str(",\"ast\":");
+    ast_to_json(&regex.ast, &mut json);
+    json.push_str(",\"code\":[");
+    for (pc, inst) in regex.code.iter().enumerate() {
+        if pc > 0 {
+            json.push(',');
+        }
+        inst_to_json(pc, inst, &mut json);
+    }
+    json.push_str("]}");
+    json
+}
+
+/// ASTをJSONのオブジェクトとして出力
+fn ast_to_json(ast: &AST, json: &mut String) {
+    match ast {
+        AST::Char(c) => write!(
+            json,
+            "{{\"type\":\"char\",\"char\":{}}}",
+            escape_json_char(*c)
+        )
+        .unwrap(),
+        AST::Class(class) => {
+            json.push_str("{\"type\":\"class\",");
+            class_to_json(class, json);
+            json.push('}');
+        }
+        AST::Any => json.push_str("{\"type\":\"any\"}"),
+        AST::AnyNewline => json.push_str("{\"type\":\"any_newline\"}"),
+        AST::Assert(assertion) => write!(
+            json,
+            "{{\"type\":\"assert\",\"assertion\":\"{}\"}}",
+            assertion_name(assertion)
+        )
+        .unwrap(),
+        AST::Plus(e, greedy) | AST::Star(e, greedy) | AST::Question(e, greedy) => {
+            let name = match ast {
+                AST::Plus(_, _) => "plus",
+                AST::Star(_, _) => "star",
+                _ => "question",
+            };
+            write!(json, "{{\"type\":\"{name}\",\"greedy\":{greedy},\"expr\":").unwrap();
+            ast_to_json(e, json);
+            json.push('}');
+        }
+        AST::Repeat(e, min, max, greedy) => {
+            let max = match max {
+                Some(max) => max.to_string(),
+                None => "null".to_string(),
+            };
+            write!(
+                json,
+                "{{\"type\":\"repeat\",\"min\":{min},\"max\":{max},\"greedy\":{greedy},\"expr\":"
+            )
+            .unwrap();
+            ast_to_json(e, json);
+            json.push('}');
+        }
+        AST::Or(e1, e2) => {
+            json.push_str("{\"type\":\"or\",\"left\":");
+            ast_to_json(e1, json);
+            json.push_str(",\"right\":");
+            ast_to_json(e2, json);
+            json.push('}');
+        }
+        AST::Seq(v) => {
+            json.push_str("{\"type\":\"seq\",\"exprs\":[");
+            for (i, e) in v.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                ast_to_json(e, json);
+            }
+            json.push_str("]}");
+        }
+        AST::Capture(n, name, e) => {
+            let name = match name {
+                Some(name) => escape_json(name),
+                None => "null".to_string(),
+            };
+            write!(
+                json,
+                "{{\"type\":\"capture\",\"index\":{n},\"name\":{name},\"expr\":"
+            )
+            .unwrap();
+            ast_to_json(e, json);
+            json.push('}');
+        }
+        AST::BackRef(n, case_insensitive) => write!(
+            json,
+            "{{\"type\":\"backref\",\"group\":{n},\"case_insensitive\":{case_insensitive}}}"
+        )
+        .unwrap(),
+        AST::Look(look, e) => {
+            json.push_str("{\"type\":\"look\",");
+            look_to_json(look, json);
+            json.push_str(",\"expr\":");
+            ast_to_json(e, json);
+            json.push('}');
+        }
+    }
+}
+
+/// 命令をJSONのオブジェクトとして出力
+fn inst_to_json(pc: usize, inst: &Instruction, json: &mut String) {
+    write!(json, "{{\"pc\":{pc},").unwrap();
+    match inst {
+        Instruction::Char(c) => {
+            write!(json, "\"op\":\"char\",\"char\":{}", escape_json_char(*c)).unwrap()
+        }
+        Instruction::Class(class) => {
+            json.push_str("\"op\":\"class\",");
+            class_to_json(class, json);
+        }
+        Instruction::Any => json.push_str("\"op\":\"any\""),
+        Instruction::AnyNewline => json.push_str("\"op\":\"any_newline\""),
+        Instruction::Assert(assertion) => write!(
+            json,
+            "\"op\":\"assert\",\"assertion\":\"{}\"",
+            assertion_name(assertion)
+        )
+        .unwrap(),
+        Instruction::Save(n) => write!(json, "\"op\":\"save\",\"slot\":{n}").unwrap(),
+        Instruction::Match(id) => write!(json, "\"op\":\"match\",\"id\":{id}").unwrap(),
+        Instruction::Jump(addr) => write!(json, "\"op\":\"jump\",\"target\":{addr}").unwrap(),
+        Instruction::Split(addr1, addr2) => {
+            write!(json, "\"op\":\"split\",\"targets\":[{addr1},{addr2}]").unwrap()
+        }
+        Instruction::BackRef(n, case_insensitive) => write!(
+            json,
+            "\"op\":\"backref\",\"group\":{n},\"case_insensitive\":{case_insensitive}"
+        )
+        .unwrap(),
+        Instruction::Look(look, addr) => {
+            json.push_str("\"op\":\"look\",");
+            look_to_json(look, json);
+            write!(json, ",\"next\":{addr}").unwrap();
+        }
+    }
+    json.push('}');
+}
+
+/// 文字クラスを、JSONのオブジェクトのnegatedとrangesのメンバとして出力
+fn class_to_json(class: &Class, json: &mut String) {
+    write!(json, "\"negated\":{},\"ranges\":[", class.negated).unwrap();
+    for (i, (start, end)) in class.ranges.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            "[{},{}]",
+            escape_json_char(*start),
+            escape_json_char(*end)
+        )
+        .unwrap();
+    }
+    json.push(']');
+}
+
+/// 先読みと後読みの種類を、JSONのオブジェクトのメンバとして出力
+///
+/// 後読みの場合は、マッチする文字数の範囲をminとmaxとして出力する
+fn look_to_json(look: &Look, json: &mut String) {
+    match look {
+        Look::Ahead | Look::NegativeAhead => write!(
+            json,
+            "\"direction\":\"ahead\",\"negative\":{}",
+            look.is_negative()
+        )
+        .unwrap(),
+        Look::Behind(min, max) | Look::NegativeBehind(min, max) => write!(
+            json,
+            "\"direction\":\"behind\",\"negative\":{},\"min\":{min},\"max\":{max}",
+            look.is_negative()
+        )
+        .unwrap(),
+    }
+}
+
+/// JSONで用いるアサーションの名前
+fn assertion_name(assertion: &Assertion) -> &'static str {
+    match assertion {
+        Assertion::Begin => "begin",
+        Assertion::End => "end",
+        Assertion::LineBegin => "line_begin",
+        Assertion::LineEnd => "line_end",
+        Assertion::WordBoundary => "word_boundary",
+        Assertion::NotWordBoundary => "not_word_boundary",
+    }
+}
+
+/// 文字を、1文字のJSONの文字列リテラルとする
+fn escape_json_char(c: char) -> String {
+    let mut buf = [0; 4];
+    escape_json(c.encode_utf8(&mut buf))
+}
+
+/// 文字列を、ダブルクォートで囲ったJSONの文字列リテラルとする
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
@@ -0,0 +1,301 @@
+//! 正規表現にマッチする文字列の生成
+//!
+//! ASTを辿りながら、選択や繰り返しの回数を疑似乱数で決めて文字列を生成する。
+//! アサーションや先読みなどは生成時に考慮しないため、生成した文字列が全体にマッチするかを
+//! 評価器で確かめ、マッチしない場合は生成し直す
+//!
+//! 同じシードからは同じ文字列の列を生成するため、テストデータの再現に利用できる
+use super::{
+    codegen,
+    evaluator::{self, EvalConfig, EvalError},
+    parser::AST,
+    regex::Regex,
+    Instruction,
+};
+use crate::helper::DynError;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 1つの文字列を生成するために試行する回数の上限
+const MAX_ATTEMPTS: usize = 1000;
+
+/// 生成した文字列が全体にマッチするかを判定する際の、深さ優先探索の命令数の上限
+///
+/// メモ化するため、後方参照を含まない場合は命令数 × 文字列長に比例する時間で判定でき、上限には達しない
+const STEP_LIMIT: usize = 1_000_000;
+
+/// 上限のない繰り返しの、既定の最大回数
+const DEFAULT_MAX_REPEAT: usize = 8;
+
+#[derive(Debug)]
+pub enum GenerateError {
+    Exhausted(usize), // 上限の回数だけ試行しても、条件を満たす文字列を生成できなかった
+}
+
+impl Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::Exhausted(attempts) => {
+                write!(
+                    f,
+                    "GenerateError: no string generated: attempts = {attempts}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for GenerateError {}
+
+impl Regex {
+    /// seedを疑似乱数のシードとして、文字列を生成するGeneratorを生成
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let re = Regex::new("[a-z]{3}-[0-9]+").unwrap();
+    /// let mut generator = re.generator(42).unwrap();
+    /// let text = generator.generate().unwrap();
+    /// assert!(re.is_match(&text).unwrap());
+    ///
+    /// let text = generator.near_miss().unwrap();
+    /// assert!(re.find(&text).unwrap().is_none_or(|m| m.as_str() != text));
+    /// ```
+    pub fn generator(&self, seed: u64) -> Result<Generator<'_>, DynError> {
+        Ok(Generator {
+            regex: self,
+            code: codegen::get_code(&self.ast)?,
+            rng: Rng::new(seed),
+            max_repeat: DEFAULT_MAX_REPEAT,
+        })
+    }
+}
+
+/// 正規表現の全体にマッチする文字列と、わずかに異なりマッチしない文字列を生成する
+pub struct Generator<'r> {
+    regex: &'r Regex,
+    code: Vec<Instruction>, // 全体にマッチするかの判定に用いる、先頭からマッチさせる命令列
+    rng: Rng,
+    max_repeat: usize, // *, +, {n,}で繰り返す回数の上限
+}
+
+impl Generator<'_> {
+    /// *, +, {n,}のような上限のない繰り返しで、最小回数に加えて繰り返す回数の上限を設定
+    ///
+    /// {n,m}の場合も、n + max_repeat回までしか繰り返さない
+    pub fn max_repeat(&mut self, max_repeat: usize) -> &mut Self {
+        self.max_repeat = max_repeat;
+        self
+    }
+
+    /// 正規表現の全体にマッチする文字列を生成
+    ///
+    /// ^やlook-aroundなどの条件を満たせず、上限の回数だけ生成し直しても
+    /// マッチする文字列が得られない場合はErrを返す。
+    /// 全体にマッチするかを命令数の上限内で判定できなかった文字列も、生成し直す
+    pub fn generate(&mut self) -> Result<String, DynError> {
+        let regex = self.regex;
+        for _ in 0..MAX_ATTEMPTS {
+            let mut text = String::new();
+            let mut groups = Vec::new();
+            if self.gen(&regex.ast, &mut text, &mut groups)
+                && self.is_full_match(&text)? == Some(true)
+            {
+                return Ok(text);
+            }
+        }
+        Err(Box::new(GenerateError::Exhausted(MAX_ATTEMPTS)))
+    }
+
+    /// マッチする文字列を1文字だけ変えた、正規表現の全体にはマッチしない文字列を生成
+    ///
+    /// 変更は、1文字の削除、挿入、置換、隣り合う文字の入れ替えのいずれかとする。
+    /// (?s).*のように任意の文字列にマッチする場合はErrを返す
+    pub fn near_miss(&mut self) -> Result<String, DynError> {
+        for _ in 0..MAX_ATTEMPTS {
+            let mut chars = self.generate()?.chars().collect::<Vec<_>>();
+            self.mutate(&mut chars);
+            let text = chars.into_iter().collect::<String>();
+            if self.is_full_match(&text)? == Some(false) {
+                return Ok(text);
+            }
+        }
+        Err(Box::new(GenerateError::Exhausted(MAX_ATTEMPTS)))
+    }
+
+    /// textが正規表現の全体にマッチするかを判定
+    ///
+    /// 後方参照により命令数の上限を超え、判定できなかった場合はOk(None)を返す
+    fn is_full_match(&self, text: &str) -> Result<Option<bool>, DynError> {
+        let config = EvalConfig {
+            step_limit: Some(STEP_LIMIT),
+            depth_limit: None,
+            memoize: true,
+        };
+        match evaluator::eval_full(&self.code, text.as_bytes(), &config) {
+            Ok(is_match) => Ok(Some(is_match)),
+            Err(e) if matches!(e.downcast_ref(), Some(EvalError::StepLimitExceeded(_))) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// astにマッチする文字列をtextの末尾に追加
+    ///
+    /// groupsには、キャプチャグループにマッチしたtext中の範囲を記録し、後方参照で用いる。
+    /// [^\s\S]のように、マッチする文字がない場合はfalseを返す
+    fn gen(
+        &mut self,
+        ast: &AST,
+        text: &mut String,
+        groups: &mut Vec<Option<(usize, usize)>>,
+    ) -> bool {
+        match ast {
+            AST::Char(c) => text.push(*c),
+            AST::Class(class) => match self.pick(&class.to_ranges()) {
+                Some(c) => text.push(c),
+                None => return false,
+            },
+            AST::Any => {
+                let c = self.pick(&[('\0', '\t'), ('\u{b}', char::MAX)]);
+                text.extend(c);
+            }
+            AST::AnyNewline => text.extend(self.pick(&[('\0', char::MAX)])),
+            // 位置に対する条件は、生成後に評価器で確かめる
+            AST::Assert(_) | AST::Look(_, _) => (),
+            AST::Plus(e, _) => return self.gen_repeat(e, 1, None, text, groups),
+            AST::Star(e, _) => return self.gen_repeat(e, 0, None, text, groups),
+            AST::Question(e, _) => return self.gen_repeat(e, 0, Some(1), text, groups),
+            AST::Repeat(e, min, max, _) => return self.gen_repeat(e, *min, *max, text, groups),
+            AST::Or(e1, e2) => {
+                let e = if self.rng.below(2) == 0 { e1 } else { e2 };
+                return self.gen(e, text, groups);
+            }
+            AST::Seq(v) => return v.iter().all(|e| self.gen(e, text, groups)),
+            AST::Capture(n, _, e) => {
+                let start = text.len();
+                if !self.gen(e, text, groups) {
+                    return false;
+                }
+                if groups.len() <= *n {
+                    groups.resize(n + 1, None);
+                }
+                groups[*n] = Some((start, text.len()));
+            }
+            AST::BackRef(n, _) => {
+                if let Some((start, end)) = groups.get(*n).copied().flatten() {
+                    let captured = text[start..end].to_string();
+                    text.push_str(&captured);
+                }
+            }
+        }
+        true
+    }
+
+    /// eをmin回からmax回まで繰り返した文字列をtextの末尾に追加
+    ///
+    /// 繰り返す回数は、最大でもmin + max_repeat回とする
+    fn gen_repeat(
+        &mut self,
+        e: &AST,
+        min: usize,
+        max: Option<usize>,
+        text: &mut String,
+        groups: &mut Vec<Option<(usize, usize)>>,
+    ) -> bool {
+        let bound = min.saturating_add(self.max_repeat);
+        let max = max.map_or(bound, |max| max.min(bound));
+        let n = self.rng.range(min, max);
+        (0..n).all(|_| self.gen(e, text, groups))
+    }
+
+    /// rangesのいずれかに含まれる文字を選ぶ
+    ///
+    /// 生成した文字列を読みやすくするため、表示可能なASCII文字を含む場合は、多くはその中から選ぶ。
+    /// rangesが空の場合はNoneを返す
+    fn pick(&mut self, ranges: &[(char, char)]) -> Option<char> {
+        let printable = ranges
+            .iter()
+            .flat_map(|(start, end)| (*start).max(' ')..=(*end).min('~'))
+            .collect::<Vec<_>>();
+        if !printable.is_empty() && self.rng.below(4) != 0 {
+            return Some(printable[self.rng.below(printable.len())]);
+        }
+
+        // 各文字が同じ確率で選ばれるよう、範囲の大きさに応じて範囲を選ぶ
+        let total = ranges
+            .iter()
+            .map(|(start, end)| *end as usize - *start as usize + 1)
+            .sum::<usize>();
+        let mut i = self.rng.below(total.max(1));
+        for (start, end) in ranges.iter() {
+            let len = *end as usize - *start as usize + 1;
+            if i < len {
+                // サロゲートの場合は、範囲の先頭の文字とする
+                return Some(char::from_u32((*start as usize + i) as u32).unwrap_or(*start));
+            }
+            i -= len;
+        }
+        None
+    }
+
+    /// charsの1文字を削除、挿入、置換するか、隣り合う2文字を入れ替える
+    fn mutate(&mut self, chars: &mut Vec<char>) {
+        // 挿入や置換に用いる文字は、元の文字列の文字、表示可能なASCII文字、改行、任意の文字のいずれかとする
+        let c = match self.rng.below(4) {
+            0 if !chars.is_empty() => chars[self.rng.below(chars.len())],
+            1 => '\n',
+            2 => self.pick(&[('\0', char::MAX)]).unwrap_or(' '),
+            _ => (' '..='~').nth(self.rng.below(95)).unwrap_or(' '),
+        };
+
+        let len = chars.len();
+        match (self.rng.below(4), len) {
+            (_, 0) | (1, _) => chars.insert(self.rng.range(0, len), c),
+            (0, _) => {
+                chars.remove(self.rng.below(len));
+            }
+            (2, _) | (3, 1) => chars[self.rng.below(len)] = c,
+            _ => {
+                let i = self.rng.below(len - 1);
+                chars.swap(i, i + 1);
+            }
+        }
+    }
+}
+
+/// xorshift64*による疑似乱数生成器
+pub(super) struct Rng(u64);
+
+impl Rng {
+    /// シードから生成器を生成
+    ///
+    /// 状態が0の場合は0しか生成しないため、シードをsplitmix64で攪拌して状態とする
+    pub(super) fn new(seed: u64) -> Rng {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Rng(if z == 0 { 0x9e37_79b9_7f4a_7c15 } else { z })
+    }
+
+    /// 次の乱数を返す
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// 0以上n未満の乱数を返す。nは1以上とする
+    pub(super) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// min以上max以下の乱数を返す
+    pub(super) fn range(&mut self, min: usize, max: usize) -> usize {
+        min + (self.next() % (max - min).saturating_add(1) as u64) as usize
+    }
+}
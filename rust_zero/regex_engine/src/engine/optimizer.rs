@@ -0,0 +1,283 @@
+//! コード生成器が出力した命令列の最適化
+//!
+//! コード生成器はASTを素直に命令列に変換するため、
+//! jumpの先がjumpであったり、a|b|cのような1文字の選択がsplitの木となったりする。
+//! ここでは、マッチ結果を変えずに命令列を短くする以下の最適化を行う
+//!
+//! - 1文字の選択の併合: a|b|cのsplitの木を、1つのclass [a-c]命令にする
+//! - ジャンプスレッディング: jumpやsplitの飛び先がjumpの場合に、最終的な飛び先へ直接飛ぶ
+//! - 不要な命令の削除: 到達不能な命令と、次の命令へのjumpを削除する
+//!
+//! また、マッチが必ず始まる文字列（リテラルプレフィックス）を求め、
+//! 評価前に文字列探索で候補位置を絞り込めるようにする
+use super::{parser::Class, Instruction};
+use crate::helper::DynError;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+#[derive(Debug)]
+pub enum OptimizeError {
+    InvalidPC(usize),
+}
+
+impl Display for OptimizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptimizeError::InvalidPC(pc) => write!(f, "OptimizeError: invalid PC: pc = {pc}"),
+        }
+    }
+}
+
+impl Error for OptimizeError {}
+
+/// 命令列を最適化する
+pub fn optimize(mut code: Vec<Instruction>) -> Result<Vec<Instruction>, DynError> {
+    validate(&code)?;
+    merge_alternations(&mut code);
+
+    // 削除によって新たにジャンプスレッディングできる場合があるため、変化がなくなるまで繰り返す
+    loop {
+        thread_jumps(&mut code);
+        let len = code.len();
+        code = remove_dead_code(code);
+        if code.len() == len {
+            return Ok(code);
+        }
+    }
+}
+
+/// すべての飛び先が命令列の範囲内にあることを確認
+fn validate(code: &[Instruction]) -> Result<(), DynError> {
+    for inst in code.iter() {
+        let addrs = match inst {
+            Instruction::Jump(addr) => vec![*addr],
+            Instruction::Split(addr1, addr2) => vec![*addr1, *addr2],
+            Instruction::Look(_, addr) => vec![*addr],
+            _ => continue,
+        };
+        if let Some(addr) = addrs.into_iter().find(|addr| *addr >= code.len()) {
+            return Err(Box::new(OptimizeError::InvalidPC(addr)));
+        }
+    }
+    Ok(())
+}
+
+/// pcの命令の次に実行される可能性のある命令のアドレスを返す
+///
+/// look命令は、部分プログラムの先頭と部分プログラムの直後の両方とする
+fn successors(code: &[Instruction], pc: usize) -> Vec<usize> {
+    match &code[pc] {
+        Instruction::Match(_) => vec![],
+        Instruction::Jump(addr) => vec![*addr],
+        Instruction::Split(addr1, addr2) => vec![*addr1, *addr2],
+        Instruction::Look(_, addr) => vec![pc + 1, *addr],
+        _ => vec![pc + 1],
+    }
+}
+
+/// 各命令に到達する経路の数を返す。0番地には評価の開始時に到達するものとする
+fn count_predecessors(code: &[Instruction]) -> Vec<usize> {
+    let mut preds = vec![0; code.len()];
+    if let Some(p) = preds.first_mut() {
+        *p = 1;
+    }
+    for pc in 0..code.len() {
+        for next in successors(code, pc) {
+            if let Some(p) = preds.get_mut(next) {
+                *p += 1;
+            }
+        }
+    }
+    preds
+}
+
+/// 1文字にマッチする命令であれば、その文字の範囲を返す
+fn single_char_ranges(inst: &Instruction) -> Option<Vec<(char, char)>> {
+    match inst {
+        Instruction::Char(c) => Some(vec![(*c, *c)]),
+        Instruction::Class(class) => Some(class.to_ranges()),
+        _ => None,
+    }
+}
+
+/// pcから始まる、1文字の選択のsplitの木を調べる
+///
+/// e1|e2のコードは以下のようになるため、e1とe2がどちらも1文字にマッチする命令の場合や、
+/// e2がさらに1文字の選択である場合に、選択全体がマッチする文字の範囲とL3を返す。
+/// 途中の命令へ他から飛んでくる場合は併合できないため、Noneを返す
+///
+/// ```text
+///     split L1, L2
+/// L1: e1
+///     jump L3
+/// L2: e2
+/// L3:
+/// ```
+fn alternation_ranges(
+    code: &[Instruction],
+    preds: &[usize],
+    pc: usize,
+) -> Option<(Vec<(char, char)>, usize)> {
+    let (l1, l2) = match code[pc] {
+        Instruction::Split(l1, l2) if l1 == pc + 1 && l2 == pc + 3 => (l1, l2),
+        _ => return None,
+    };
+    let l3 = match code.get(l1 + 1) {
+        Some(Instruction::Jump(l3)) => *l3,
+        _ => return None,
+    };
+    if preds[l1] != 1 || preds[l1 + 1] != 1 || preds[l2] != 1 {
+        return None;
+    }
+
+    let mut ranges = single_char_ranges(&code[l1])?;
+    if let Some(r) = single_char_ranges(&code[l2]) {
+        if l2 + 1 != l3 {
+            return None;
+        }
+        ranges.extend(r);
+    } else {
+        let (r, end) = alternation_ranges(code, preds, l2)?;
+        if end != l3 {
+            return None;
+        }
+        ranges.extend(r);
+    }
+    Some((ranges, l3))
+}
+
+/// 1文字の選択のsplitの木を、1つのclass命令とその後のjumpにする
+///
+/// 併合したsplitの木の残りの命令は到達不能となるため、remove_dead_codeで削除される。
+/// 1文字の選択はどの枝も1文字を消費して同じ位置に進むため、枝の優先順位はマッチ結果に影響しない
+fn merge_alternations(code: &mut [Instruction]) {
+    let preds = count_predecessors(code);
+    let mut pc = 0;
+    while pc < code.len() {
+        if let Some((ranges, end)) = alternation_ranges(code, &preds, pc) {
+            let class = Class {
+                ranges,
+                negated: false,
+            };
+            code[pc] = Instruction::Class(Class {
+                ranges: class.to_ranges(),
+                negated: false,
+            });
+            code[pc + 1] = Instruction::Jump(end);
+            pc = end;
+        } else {
+            pc += 1;
+        }
+    }
+}
+
+/// 飛び先がjumpの場合に、最終的な飛び先を返す
+fn final_target(code: &[Instruction], mut addr: usize) -> usize {
+    // jumpの無限ループに備え、命令数までしか辿らない
+    for _ in 0..code.len() {
+        match code[addr] {
+            Instruction::Jump(next) if next != addr => addr = next,
+            _ => break,
+        }
+    }
+    addr
+}
+
+/// jump、split、lookの飛び先がjumpの場合に、最終的な飛び先へ直接飛ぶようにする
+///
+/// また、matchへのjumpはmatchに、飛び先が同じsplitはjumpに置き換える
+fn thread_jumps(code: &mut [Instruction]) {
+    for pc in 0..code.len() {
+        let new = match code[pc] {
+            Instruction::Jump(addr) => {
+                let addr = final_target(code, addr);
+                if let Instruction::Match(id) = code[addr] {
+                    Instruction::Match(id)
+                } else {
+                    Instruction::Jump(addr)
+                }
+            }
+            Instruction::Split(addr1, addr2) => {
+                let addr1 = final_target(code, addr1);
+                let addr2 = final_target(code, addr2);
+                if addr1 == addr2 {
+                    Instruction::Jump(addr1)
+                } else {
+                    Instruction::Split(addr1, addr2)
+                }
+            }
+            Instruction::Look(look, addr) => Instruction::Look(look, final_target(code, addr)),
+            _ => continue,
+        };
+        code[pc] = new;
+    }
+}
+
+/// 到達不能な命令と、次の命令へのjumpを削除し、飛び先のアドレスを付け替える
+fn remove_dead_code(code: Vec<Instruction>) -> Vec<Instruction> {
+    // 0番地から到達可能な命令を求める
+    let mut reachable = vec![false; code.len()];
+    let mut stack = vec![0];
+    while let Some(pc) = stack.pop() {
+        if pc >= code.len() || reachable[pc] {
+            continue;
+        }
+        reachable[pc] = true;
+        stack.extend(successors(&code, pc));
+    }
+
+    let keep = (0..code.len())
+        .map(|pc| reachable[pc] && !matches!(code[pc], Instruction::Jump(addr) if addr == pc + 1))
+        .collect::<Vec<bool>>();
+
+    // 削除した命令へのjumpは、その後で最初に残る命令へのjumpとする
+    let mut new_addr = vec![0; code.len() + 1];
+    let mut n = 0;
+    for pc in 0..code.len() {
+        new_addr[pc] = n;
+        if keep[pc] {
+            n += 1;
+        }
+    }
+    new_addr[code.len()] = n;
+
+    code.into_iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(inst, _)| match inst {
+            Instruction::Jump(addr) => Instruction::Jump(new_addr[addr]),
+            Instruction::Split(addr1, addr2) => {
+                Instruction::Split(new_addr[addr1], new_addr[addr2])
+            }
+            Instruction::Look(look, addr) => Instruction::Look(look, new_addr[addr]),
+            inst => inst,
+        })
+        .collect()
+}
+
+/// 先頭からマッチさせる命令列codeについて、マッチが必ず始まる文字列を返す
+///
+/// 0番地からsaveとjumpを読み飛ばしつつ、charが続く限り文字を集める。
+/// 文字を集められない場合は空文字列を返す
+///
+/// バイト列を評価する場合はUTF-8として不正なバイトもU+FFFDにマッチするため、
+/// U+FFFDより後ろはプレフィックスに含めない
+pub fn literal_prefix(code: &[Instruction]) -> String {
+    let mut prefix = String::new();
+    let mut pc = 0;
+    // jumpの無限ループに備え、命令数までしか辿らない
+    for _ in 0..code.len() {
+        match code.get(pc) {
+            Some(Instruction::Char(c)) if *c != char::REPLACEMENT_CHARACTER => {
+                prefix.push(*c);
+                pc += 1;
+            }
+            Some(Instruction::Save(_)) => pc += 1,
+            Some(Instruction::Jump(addr)) => pc = *addr,
+            _ => break,
+        }
+    }
+    prefix
+}
@@ -1,96 +1,1140 @@
 //! 正規表現の式をパースし、抽象構文気に変換
 use std::{
+    collections::HashMap,
     error::Error,
     fmt::{self, Display},
+    iter::{Enumerate, Peekable},
     mem::take,
+    str::Chars,
+    sync::OnceLock,
 };
 
+use super::unicode_tables;
 use crate::helper::DynError;
 
+/// パース時に指定するフラグ
+///
+/// 正規表現中では(?i)や(?i:...)のように指定する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    pub case_insensitive: bool,     // i: 大文字と小文字を区別しない
+    pub multi_line: bool,           // m: ^と$を行頭と行末にもマッチさせる
+    pub dot_matches_new_line: bool, // s: .を改行にもマッチさせる
+}
+
 /// 抽象構文木を表現するための型
-#[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone)]
 pub enum AST {
     Char(char),
-    Plus(Box<AST>),
-    Star(Box<AST>),
-    Question(Box<AST>),
+    Class(Class),
+    Any,        // .: 改行以外の任意の1文字
+    AnyNewline, // (?s).: 改行を含む任意の1文字
+    Assert(Assertion),
+    // 限量子のboolは貪欲かどうかを表し、e+?のような非貪欲な場合はfalseとなる
+    Plus(Box<AST>, bool),
+    Star(Box<AST>, bool),
+    Question(Box<AST>, bool),
+    Repeat(Box<AST>, usize, Option<usize>, bool), // (式, 最小回数, 最大回数, 貪欲かどうか)。最大回数がNoneの場合は上限なし
     Or(Box<AST>, Box<AST>),
     Seq(Vec<AST>),
+    Capture(usize, Option<String>, Box<AST>), // (グループ番号, グループ名, 式)
+    BackRef(usize, bool), // \1, \k<name>: (グループ番号, 大文字と小文字を区別しないか)
+    Look(Look, Box<AST>), // (?=...), (?!...), (?<=...), (?<!...): (種類, 式)
+}
+
+impl AST {
+    /// キャプチャグループの名前を、グループ番号順に返す
+    ///
+    /// 0番目はマッチ全体を表すため常にNoneとなり、
+    /// 名前のないグループもNoneとなる
+    pub fn capture_names(&self) -> Vec<Option<String>> {
+        fn collect(ast: &AST, names: &mut Vec<Option<String>>) {
+            match ast {
+                AST::Plus(e, _)
+                | AST::Star(e, _)
+                | AST::Question(e, _)
+                | AST::Repeat(e, _, _, _)
+                | AST::Look(_, e) => collect(e, names),
+                AST::Or(e1, e2) => {
+                    collect(e1, names);
+                    collect(e2, names);
+                }
+                AST::Seq(v) => v.iter().for_each(|e| collect(e, names)),
+                AST::Capture(n, name, e) => {
+                    if names.len() <= *n {
+                        names.resize(*n + 1, None);
+                    }
+                    names[*n] = name.clone();
+                    collect(e, names);
+                }
+                AST::Char(_)
+                | AST::Class(_)
+                | AST::Any
+                | AST::AnyNewline
+                | AST::Assert(_)
+                | AST::BackRef(_, _) => (),
+            }
+        }
+
+        let mut names = vec![None];
+        collect(self, &mut names);
+        names
+    }
+
+    /// この式にマッチする文字列の文字数の範囲を(最小, 最大)として返す
+    ///
+    /// e*のように上限がない場合や、後方参照のように文字数が定まらない場合は、最大をNoneとする
+    pub(super) fn match_len(&self) -> (usize, Option<usize>) {
+        // eをmin回からmax回繰り返した場合の文字数の範囲
+        fn repeat(e: &AST, min: usize, max: Option<usize>) -> (usize, Option<usize>) {
+            let (emin, emax) = e.match_len();
+            let max = match (emax, max) {
+                (Some(0), _) => Some(0),
+                (Some(emax), Some(max)) => emax.checked_mul(max),
+                _ => None,
+            };
+            (emin.saturating_mul(min), max)
+        }
+
+        match self {
+            AST::Char(_) | AST::Class(_) | AST::Any | AST::AnyNewline => (1, Some(1)),
+            AST::Assert(_) | AST::Look(_, _) => (0, Some(0)),
+            AST::BackRef(_, _) => (0, None),
+            AST::Plus(e, _) => repeat(e, 1, None),
+            AST::Star(e, _) => repeat(e, 0, None),
+            AST::Question(e, _) => repeat(e, 0, Some(1)),
+            AST::Repeat(e, min, max, _) => repeat(e, *min, *max),
+            AST::Or(e1, e2) => {
+                let (min1, max1) = e1.match_len();
+                let (min2, max2) = e2.match_len();
+                (min1.min(min2), max1.zip(max2).map(|(m1, m2)| m1.max(m2)))
+            }
+            AST::Seq(v) => v.iter().fold((0, Some(0)), |(min, max), e| {
+                let (emin, emax) = e.match_len();
+                let max = max.zip(emax).and_then(|(m, em)| m.checked_add(em));
+                (min.saturating_add(emin), max)
+            }),
+            AST::Capture(_, _, e) => e.match_len(),
+        }
+    }
+}
+
+/// 再びパースすると同じ意味のASTとなる、正規表現の形式で表示する
+///
+/// 括弧は優先順位を保つのに必要な箇所にのみ(?:...)を付加する。
+/// ただし、(?:)*のように空の式を限量子で修飾したものは、パースできない形式となる
+impl Display for AST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /// 限量子で修飾する式を表示する。1文字や括弧以外は(?:...)で囲む
+        fn write_atom(f: &mut fmt::Formatter<'_>, e: &AST) -> fmt::Result {
+            match e {
+                AST::Char(_)
+                | AST::Class(_)
+                | AST::Any
+                | AST::AnyNewline
+                | AST::Assert(_)
+                | AST::Capture(_, _, _)
+                | AST::BackRef(_, false)
+                | AST::Look(_, _) => write!(f, "{e}"),
+                _ => write!(f, "(?:{e})"),
+            }
+        }
+
+        /// 限量子を表示する
+        fn write_quantifier(
+            f: &mut fmt::Formatter<'_>,
+            e: &AST,
+            quantifier: &str,
+            greedy: bool,
+        ) -> fmt::Result {
+            write_atom(f, e)?;
+            write!(f, "{quantifier}")?;
+            if !greedy {
+                write!(f, "?")?;
+            }
+            Ok(())
+        }
+
+        match self {
+            AST::Char(c) => match c {
+                '\\' | '(' | ')' | '|' | '+' | '*' | '?' | '[' | ']' | '{' | '}' | '-' | '^'
+                | '$' | '.' => write!(f, "\\{c}"),
+                '\n' => write!(f, "\\n"),
+                '\t' => write!(f, "\\t"),
+                _ if c.is_control() => write!(f, "\\u{{{:x}}}", *c as u32),
+                _ => write!(f, "{c}"),
+            },
+            AST::Class(class) => write!(f, "{class}"),
+            AST::Any => write!(f, "."),
+            AST::AnyNewline => write!(f, "(?s:.)"),
+            AST::Assert(assertion) => write!(f, "{assertion}"),
+            AST::Plus(e, greedy) => write_quantifier(f, e, "+", *greedy),
+            AST::Star(e, greedy) => write_quantifier(f, e, "*", *greedy),
+            AST::Question(e, greedy) => write_quantifier(f, e, "?", *greedy),
+            AST::Repeat(e, min, max, greedy) => {
+                let quantifier = match max {
+                    Some(max) if max == min => format!("{{{min}}}"),
+                    Some(max) => format!("{{{min},{max}}}"),
+                    None => format!("{{{min},}}"),
+                };
+                write_quantifier(f, e, &quantifier, *greedy)
+            }
+            AST::Or(e1, e2) => write!(f, "{e1}|{e2}"),
+            AST::Seq(v) => {
+                if v.is_empty() {
+                    return write!(f, "(?:)");
+                }
+                for e in v.iter() {
+                    match e {
+                        AST::Or(_, _) => write!(f, "(?:{e})")?,
+                        _ => write!(f, "{e}")?,
+                    }
+                }
+                Ok(())
+            }
+            AST::Capture(_, Some(name), e) => write!(f, "(?P<{name}>{e})"),
+            AST::Capture(_, None, e) => write!(f, "({e})"),
+            AST::BackRef(n, false) => write!(f, "\\{n}"),
+            AST::BackRef(n, true) => write!(f, "(?i:\\{n})"),
+            AST::Look(look, e) => match look {
+                Look::Ahead => write!(f, "(?={e})"),
+                Look::NegativeAhead => write!(f, "(?!{e})"),
+                Look::Behind(_, _) => write!(f, "(?<={e})"),
+                Look::NegativeBehind(_, _) => write!(f, "(?<!{e})"),
+            },
+        }
+    }
+}
+
+/// 文字を消費せず、位置に対する条件のみを検査するアサーション
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assertion {
+    Begin,           // ^: 文字列の先頭
+    End,             // $: 文字列の末尾
+    LineBegin,       // (?m)^: 文字列か行の先頭
+    LineEnd,         // (?m)$: 文字列か行の末尾
+    WordBoundary,    // \b: 単語の境界
+    NotWordBoundary, // \B: 単語の境界以外
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::Begin => write!(f, "^"),
+            Assertion::End => write!(f, "$"),
+            Assertion::LineBegin => write!(f, "(?m:^)"),
+            Assertion::LineEnd => write!(f, "(?m:$)"),
+            Assertion::WordBoundary => write!(f, "\\b"),
+            Assertion::NotWordBoundary => write!(f, "\\B"),
+        }
+    }
+}
+
+/// 先読みと後読みの種類
+///
+/// 後読みは、括弧内の式にマッチする文字数の範囲(最小, 最大)を持つ。
+/// 評価器は、現在位置からその文字数だけ前の各位置から括弧内の式を評価する
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Look {
+    Ahead,                        // (?=...): 肯定先読み
+    NegativeAhead,                // (?!...): 否定先読み
+    Behind(usize, usize),         // (?<=...): 肯定後読み
+    NegativeBehind(usize, usize), // (?<!...): 否定後読み
+}
+
+impl Look {
+    /// 否定の先読み、後読みかを判定
+    pub fn is_negative(&self) -> bool {
+        matches!(self, Look::NegativeAhead | Look::NegativeBehind(_, _))
+    }
+}
+
+impl Display for Look {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Look::Ahead => write!(f, "ahead"),
+            Look::NegativeAhead => write!(f, "not ahead"),
+            Look::Behind(min, max) => write!(f, "behind {min}..={max}"),
+            Look::NegativeBehind(min, max) => write!(f, "not behind {min}..={max}"),
+        }
+    }
+}
+
+/// 文字クラスを表現するための型
+///
+/// rangesは文字の範囲を(開始, 終了)の閉区間で表したもの。
+/// 例えば、[a-z0-9_]は[('a', 'z'), ('0', '9'), ('_', '_')]となる。
+/// negatedがtrueの場合は、[^abc]のような否定クラスを表す
+#[derive(Debug, Clone)]
+pub struct Class {
+    pub ranges: Vec<(char, char)>,
+    pub negated: bool,
+}
+
+impl Class {
+    /// 文字cがこのクラスにマッチするかを判定
+    pub fn is_match(&self, c: char) -> bool {
+        let contains = self
+            .ranges
+            .iter()
+            .any(|(start, end)| *start <= c && c <= *end);
+        contains != self.negated
+    }
+
+    /// このクラスにマッチする文字の範囲を、重複のない昇順の列として返す
+    ///
+    /// 否定クラスの場合は補集合を計算して返すため、
+    /// [^\x00-a]のようなクラスは[('b', '\u{10ffff}')]となる
+    pub fn to_ranges(&self) -> Vec<(char, char)> {
+        let mut ranges = self.ranges.clone();
+        ranges.sort();
+
+        // 重なり合う範囲や隣接する範囲を結合
+        let mut merged: Vec<(char, char)> = Vec::new();
+        for (start, end) in ranges {
+            if let Some((_, last_end)) = merged.last_mut() {
+                if next_char(*last_end).is_none_or(|c| start <= c) {
+                    if end > *last_end {
+                        *last_end = end;
+                    }
+                    continue;
+                }
+            }
+            merged.push((start, end));
+        }
+
+        if !self.negated {
+            return merged;
+        }
+
+        // 補集合を計算
+        let mut complement = Vec::new();
+        let mut next = Some('\0');
+        for (start, end) in merged {
+            if let Some(n) = next {
+                if n < start {
+                    complement.push((n, prev_char(start).unwrap()));
+                }
+            }
+            next = next_char(end);
+        }
+        if let Some(n) = next {
+            complement.push((n, char::MAX));
+        }
+        complement
+    }
+
+    /// 大文字と小文字を区別しないよう、各文字の大文字と小文字を加えたクラスを返す
+    ///
+    /// 否定クラスの場合は、否定する前の文字の集合に加える
+    pub fn case_fold(&self) -> Class {
+        let mut ranges = self.ranges.clone();
+        for (c, orbit) in case_folding_table().iter() {
+            if self
+                .ranges
+                .iter()
+                .any(|(start, end)| start <= c && c <= end)
+            {
+                ranges.extend(orbit.iter().map(|v| (*v, *v)));
+            }
+        }
+        Class {
+            ranges: Class {
+                ranges,
+                negated: false,
+            }
+            .to_ranges(),
+            negated: self.negated,
+        }
+    }
+}
+
+/// 大文字と小文字を区別しない場合に、同一視する文字の集合の表
+///
+/// Unicodeの単純ケースフォールディング（1文字から1文字への変換）で、
+/// 同じ文字に変換される文字を同一視する。
+/// 例えば、k、K、K（ケルビン記号）は同じ集合となる。
+/// 各文字から、その文字を含む集合（昇順）への対応を初回利用時に生成する
+fn case_folding_table() -> &'static HashMap<char, Vec<char>> {
+    static TABLE: OnceLock<HashMap<char, Vec<char>>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut orbits: HashMap<char, Vec<char>> = HashMap::new();
+        // 大文字と小文字のある文字は、すべて基本多言語面と追加多言語面にある
+        for c in '\0'..='\u{1ffff}' {
+            let folded = simple_fold(c);
+            if folded != c {
+                orbits.entry(folded).or_insert_with(|| vec![folded]).push(c);
+            }
+        }
+
+        let mut table = HashMap::new();
+        for mut orbit in orbits.into_values() {
+            orbit.sort_unstable();
+            for c in orbit.iter() {
+                table.insert(*c, orbit.clone());
+            }
+        }
+        table
+    })
+}
+
+/// cを単純ケースフォールディングした文字を返す
+///
+/// 大文字にしてから小文字にすることで、ς（語末のシグマ）とσのように、
+/// 小文字同士で同一視すべき文字も同じ文字に変換する。
+/// ßの大文字がSSとなるような、1文字に対応しない変換は用いない
+fn simple_fold(c: char) -> char {
+    // トルコ語のı（ドットなしのi）は、iと同一視しない
+    if c == '\u{131}' {
+        return c;
+    }
+
+    let lower = |c: char| {
+        let mut it = c.to_lowercase();
+        match (it.next(), it.next()) {
+            (Some(l), None) => l,
+            _ => c,
+        }
+    };
+    let mut it = c.to_uppercase();
+    match (it.next(), it.next()) {
+        (Some(u), None) => lower(u),
+        _ => lower(c),
+    }
+}
+
+/// 大文字と小文字を区別しない場合に、cと同一視する文字（c自身は除く）を返す
+fn case_variants(c: char) -> Vec<char> {
+    match case_folding_table().get(&c) {
+        Some(orbit) => orbit.iter().copied().filter(|v| *v != c).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// 大文字と小文字を区別せずに、文字aとbが同じかを判定
+pub fn case_fold_eq(a: char, b: char) -> bool {
+    a == b
+        || case_folding_table()
+            .get(&a)
+            .is_some_and(|orbit| orbit.contains(&b))
+}
+
+/// cの次のUnicodeスカラー値を返す（サロゲート領域は飛ばす）
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{d7ff}' => Some('\u{e000}'),
+        char::MAX => None,
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// cの前のUnicodeスカラー値を返す（サロゲート領域は飛ばす）
+fn prev_char(c: char) -> Option<char> {
+    match c {
+        '\u{e000}' => Some('\u{d7ff}'),
+        '\0' => None,
+        _ => char::from_u32(c as u32 - 1),
+    }
+}
+
+/// 文字クラスを[^a-z]のような形式で表示する
+impl Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_char(f: &mut fmt::Formatter<'_>, c: char) -> fmt::Result {
+            match c {
+                '\\' | ']' | '[' | '-' | '^' => write!(f, "\\{c}"),
+                _ => write!(f, "{c}"),
+            }
+        }
+
+        write!(f, "[")?;
+        if self.negated {
+            write!(f, "^")?;
+        }
+        for (start, end) in self.ranges.iter() {
+            write_char(f, *start)?;
+            if start != end {
+                write!(f, "-")?;
+                write_char(f, *end)?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// 正規表現中の範囲を表すための型
+///
+/// startとendは正規表現の先頭から数えた文字単位の位置で、endは範囲に含まない
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// 3..5のような形式で表示する
+impl Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
 }
 
 /// パースエラーを表すための型
+///
+/// いずれもエラーの原因となった正規表現中の範囲を持つ
 #[derive(Debug)]
 pub enum ParseError {
-    InvalidEscape(usize, char), // 誤ったエスケープシーケンス
-    InvalidRightParen(usize),   // 開き括弧なし
-    NoPrev(usize),              // +, |, *, ?の前に式がない
-    NoRightParen,               // 閉じ括弧なし
-    Empty,                      // 空のパターン
+    InvalidEscape(Span, char),              // 誤ったエスケープシーケンス
+    InvalidRightParen(Span),                // 開き括弧なし
+    NoPrev(Span),                           // +, |, *, ?の前に式がない
+    NoRightParen(Span),                     // 閉じ括弧なし。範囲は閉じられていない開き括弧
+    Empty(Span),                            // 空のパターン
+    UnclosedClass(Span),                    // 文字クラスの閉じ括弧なし
+    InvalidRange(Span, char, char),         // [z-a]のように、範囲の開始が終了より大きい
+    InvalidCodePoint(Span),                 // \u{...}の値が不正
+    InvalidProperty(Span),                  // \p{...}の書式が不正か、プロパティ名が未定義
+    InvalidGroup(Span),                     // (?の後が不正
+    InvalidGroupName(Span),                 // (?P<name>...)のグループ名が不正
+    DuplicateGroupName(Span, String),       // 同じグループ名が複数ある
+    InvalidRepeat(Span),                    // {n,m}の書式が不正
+    InvalidRepeatRange(Span, usize, usize), // {n,m}でnがmより大きい
+    NestedQuantifier(Span),                 // a**のように、限量子が連続している
+    IncompleteEscape(Span),                 // 末尾が\で終わっている
+    InvalidBackRef(Span),                   // 後方参照の参照先のグループがない
+    UnboundedLookBehind(Span),              // 後読みの式にマッチする文字数に上限がない
+}
+
+impl ParseError {
+    /// エラーの原因となった正規表現中の範囲を返す
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::InvalidEscape(span, _)
+            | ParseError::InvalidRightParen(span)
+            | ParseError::NoPrev(span)
+            | ParseError::NoRightParen(span)
+            | ParseError::Empty(span)
+            | ParseError::UnclosedClass(span)
+            | ParseError::InvalidRange(span, _, _)
+            | ParseError::InvalidCodePoint(span)
+            | ParseError::InvalidProperty(span)
+            | ParseError::InvalidGroup(span)
+            | ParseError::InvalidGroupName(span)
+            | ParseError::DuplicateGroupName(span, _)
+            | ParseError::InvalidRepeat(span)
+            | ParseError::InvalidRepeatRange(span, _, _)
+            | ParseError::NestedQuantifier(span)
+            | ParseError::IncompleteEscape(span)
+            | ParseError::InvalidBackRef(span)
+            | ParseError::UnboundedLookBehind(span) => *span,
+        }
+    }
+
+    /// エラーメッセージの下に正規表現exprを表示し、
+    /// さらにその下のエラーの範囲に^を表示した文字列を返す
+    ///
+    /// 全角文字は2桁分として位置を揃える
+    ///
+    /// ```
+    /// use regex_engine::engine::{ParseError, Regex};
+    /// let err = Regex::new("あ(b**)").unwrap_err();
+    /// let err = err.downcast_ref::<ParseError>().unwrap();
+    /// assert_eq!(
+    ///     err.render("あ(b**)"),
+    ///     "ParseError: nested quantifier: pos = 4..5\n  あ(b**)\n       ^"
+    /// );
+    /// ```
+    pub fn render(&self, expr: &str) -> String {
+        let span = self.span();
+        let mut line = String::new();
+        let mut caret = String::new();
+        for (i, c) in expr.chars().enumerate() {
+            // 制御文字はそのまま表示すると位置がずれるため、エスケープして表示
+            let shown = if c.is_control() {
+                c.escape_default().collect()
+            } else {
+                c.to_string()
+            };
+            let width = shown.chars().map(char_width).sum::<usize>();
+            line.push_str(&shown);
+
+            let mark = if span.start <= i && i < span.end {
+                '^'
+            } else if i < span.start {
+                ' '
+            } else {
+                continue;
+            };
+            caret.extend(std::iter::repeat_n(mark, width));
+        }
+
+        // 範囲が空の場合や、範囲が末尾を指している場合も、その位置に^を1つ表示
+        if span.start >= span.end || span.start >= expr.chars().count() {
+            caret.push('^');
+        }
+        format!("{self}\n  {line}\n  {}", caret.trim_end())
+    }
+}
+
+/// 端末に表示した際の文字の幅を返す
+///
+/// 東アジアの文字幅がWide、Fullwidthとなる主な範囲を2とし、それ以外を1とする
+fn char_width(c: char) -> usize {
+    match c {
+        '\u{1100}'..='\u{115f}'
+        | '\u{2e80}'..='\u{303e}'
+        | '\u{3041}'..='\u{33ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{a000}'..='\u{a4cf}'
+        | '\u{ac00}'..='\u{d7a3}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{fe30}'..='\u{fe4f}'
+        | '\u{ff00}'..='\u{ff60}'
+        | '\u{ffe0}'..='\u{ffe6}'
+        | '\u{1f300}'..='\u{1f64f}'
+        | '\u{20000}'..='\u{3fffd}' => 2,
+        _ => 1,
+    }
 }
 
 /// パースエラーを表示するために、Displayトレイトを実装
 impl Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ParseError::InvalidEscape(pos, c) => {
-                write!(f, "ParseError: invalid escape: pos = {pos}, char = '{c}'")
+            ParseError::InvalidEscape(span, c) => {
+                write!(f, "ParseError: invalid escape: pos = {span}, char = '{c}'")
+            }
+            ParseError::InvalidRightParen(span) => {
+                write!(f, "ParseError: invalid right parenthesis: pos = {span}")
+            }
+            ParseError::NoPrev(span) => {
+                write!(f, "ParseError: no previous expression: pos = {span}")
+            }
+            ParseError::NoRightParen(span) => {
+                write!(f, "ParseError: no right parenthesis: pos = {span}")
+            }
+            ParseError::Empty(span) => write!(f, "ParseError: empty expression: pos = {span}"),
+            ParseError::UnclosedClass(span) => {
+                write!(f, "ParseError: unclosed character class: pos = {span}")
+            }
+            ParseError::InvalidRange(span, start, end) => {
+                write!(
+                    f,
+                    "ParseError: invalid range: pos = {span}, range = '{start}-{end}'"
+                )
+            }
+            ParseError::InvalidCodePoint(span) => {
+                write!(f, "ParseError: invalid code point: pos = {span}")
+            }
+            ParseError::InvalidProperty(span) => {
+                write!(f, "ParseError: invalid unicode property: pos = {span}")
+            }
+            ParseError::InvalidGroup(span) => {
+                write!(f, "ParseError: invalid group: pos = {span}")
             }
-            ParseError::InvalidRightParen(pos) => {
-                write!(f, "ParseError: invalid right parenthesis: pos = {pos}")
+            ParseError::InvalidGroupName(span) => {
+                write!(f, "ParseError: invalid group name: pos = {span}")
             }
-            ParseError::NoPrev(pos) => {
-                write!(f, "ParseError: no previous expression: pos = {pos}")
+            ParseError::InvalidRepeat(span) => {
+                write!(f, "ParseError: invalid repetition: pos = {span}")
             }
-            ParseError::NoRightParen => {
-                write!(f, "ParseError: no right parenthesis")
+            ParseError::InvalidRepeatRange(span, min, max) => {
+                write!(
+                    f,
+                    "ParseError: invalid repetition range: pos = {span}, range = {{{min},{max}}}"
+                )
+            }
+            ParseError::DuplicateGroupName(span, name) => {
+                write!(
+                    f,
+                    "ParseError: duplicate group name: pos = {span}, name = '{name}'"
+                )
+            }
+            ParseError::NestedQuantifier(span) => {
+                write!(f, "ParseError: nested quantifier: pos = {span}")
+            }
+            ParseError::IncompleteEscape(span) => {
+                write!(f, "ParseError: incomplete escape: pos = {span}")
+            }
+            ParseError::InvalidBackRef(span) => {
+                write!(f, "ParseError: invalid back reference: pos = {span}")
+            }
+            ParseError::UnboundedLookBehind(span) => {
+                write!(f, "ParseError: unbounded look-behind: pos = {span}")
             }
-            ParseError::Empty => write!(f, "ParseError: empty expression"),
         }
     }
 }
 
 impl Error for ParseError {} // エラー用にErrorとレイトを実装
 
-/// 特殊文字のエスケープ
-fn parse_escape(pos: usize, c: char) -> Result<AST, ParseError> {
+/// パース中の文字列を、位置付きで先読みしながら辿るためのイテレータ
+#[derive(Clone)]
+struct ExprChars<'a> {
+    chars: Peekable<Enumerate<Chars<'a>>>,
+    len: usize, // 文字列の文字数
+}
+
+impl<'a> ExprChars<'a> {
+    fn new(expr: &'a str) -> Self {
+        ExprChars {
+            chars: expr.chars().enumerate().peekable(),
+            len: expr.chars().count(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<&(usize, char)> {
+        self.chars.peek()
+    }
+
+    /// 次に読み込む文字の位置を返す。すべて読み込んだ場合は文字数を返す
+    ///
+    /// エラーの範囲の終了位置として用いる
+    fn pos(&mut self) -> usize {
+        match self.chars.peek() {
+            Some((i, _)) => *i,
+            None => self.len,
+        }
+    }
+}
+
+impl Iterator for ExprChars<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.chars.next()
+    }
+}
+
+/// \d, \w, \sなどの定義済み文字クラスを返す
+///
+/// 大文字の場合は否定クラスとなる。いずれもASCIIの範囲のみを対象とする
+fn perl_class(c: char) -> Option<Class> {
+    let ranges = match c.to_ascii_lowercase() {
+        'd' => vec![('0', '9')],
+        'w' => vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+        's' => vec![('\t', '\r'), (' ', ' ')], // \t, \n, \v, \f, \r, 空白
+        _ => return None,
+    };
+    Some(Class {
+        ranges,
+        negated: c.is_ascii_uppercase(),
+    })
+}
+
+/// \u{3042}のようなUnicodeエスケープをパース
+///
+/// posは\uのuの位置で、charsはuの直後を指している。
+/// エラーの範囲は\から不正な文字までとする
+fn parse_unicode(chars: &mut ExprChars, pos: usize) -> Result<char, ParseError> {
+    if !matches!(chars.next(), Some((_, '{'))) {
+        return Err(ParseError::InvalidCodePoint(Span::new(
+            pos - 1,
+            chars.pos(),
+        )));
+    }
+
+    let mut hex = String::new();
+    loop {
+        match chars.next() {
+            Some((_, '}')) => break,
+            Some((_, c)) if c.is_ascii_hexdigit() && hex.len() < 6 => hex.push(c),
+            _ => {
+                return Err(ParseError::InvalidCodePoint(Span::new(
+                    pos - 1,
+                    chars.pos(),
+                )))
+            }
+        }
+    }
+
+    u32::from_str_radix(&hex, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or(ParseError::InvalidCodePoint(Span::new(
+            pos - 1,
+            chars.pos(),
+        )))
+}
+
+/// \p{Hiragana}や\pLのようなUnicodeプロパティのクラスをパース
+///
+/// posは\pのpの位置で、cはpかPのいずれか。charsはpの直後を指している。
+/// プロパティ名が1文字の場合は括弧を省略できる。Pの場合は否定クラスとなる。
+/// エラーの範囲は\からプロパティ名の終わりまでとする
+fn parse_property(chars: &mut ExprChars, pos: usize, c: char) -> Result<Class, ParseError> {
+    let mut name = String::new();
+    match chars.next() {
+        Some((_, '{')) => loop {
+            match chars.next() {
+                Some((_, '}')) => break,
+                Some((_, c)) if c.is_ascii_alphanumeric() || c == '_' => name.push(c),
+                _ => return Err(ParseError::InvalidProperty(Span::new(pos - 1, chars.pos()))),
+            }
+        },
+        Some((_, c)) => name.push(c),
+        None => (),
+    }
+
+    unicode_tables::PROPERTIES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, ranges)| Class {
+            ranges: ranges.to_vec(),
+            negated: c == 'P',
+        })
+        .ok_or(ParseError::InvalidProperty(Span::new(pos - 1, chars.pos())))
+}
+
+/// エスケープシーケンスをASTに変換
+///
+/// posは\の直後の文字の位置で、cはその文字。
+/// 特殊文字のエスケープ、\n, \t, \u{...}はAST::Charに、
+/// \d, \D, \w, \W, \s, \S, \p{...}, \P{...}はAST::Classに変換する
+fn parse_escape(chars: &mut ExprChars, pos: usize, c: char) -> Result<AST, ParseError> {
     match c {
-        '\\' | '(' | ')' | '|' | '+' | '*' | '?' => Ok(AST::Char(c)),
+        '\\' | '(' | ')' | '|' | '+' | '*' | '?' | '[' | ']' | '{' | '}' | '-' | '^' | '$'
+        | '.' => Ok(AST::Char(c)),
+        'n' => Ok(AST::Char('\n')),
+        't' => Ok(AST::Char('\t')),
+        'u' => Ok(AST::Char(parse_unicode(chars, pos)?)),
+        'p' | 'P' => Ok(AST::Class(parse_property(chars, pos, c)?)),
         _ => {
-            let err = ParseError::InvalidEscape(pos, c);
-            Err(err)
+            if let Some(class) = perl_class(c) {
+                Ok(AST::Class(class))
+            } else {
+                Err(ParseError::InvalidEscape(Span::new(pos - 1, pos + 1), c))
+            }
+        }
+    }
+}
+
+/// \1から\9と\k<name>の後方参照をパース
+///
+/// posは\の直後の文字の位置で、cはその文字。charsはcの直後を指している。
+/// 参照先は、開き括弧が後方参照より前にあるグループに限る。
+/// ncapはそれまでに現れたグループの数、namesはグループ名からグループ番号への対応
+///
+/// \10は\1の後に0が続くものとして扱う
+fn parse_backref(
+    chars: &mut ExprChars,
+    pos: usize,
+    c: char,
+    ncap: usize,
+    names: &HashMap<String, usize>,
+) -> Result<AST, ParseError> {
+    let n = if let Some(d) = c.to_digit(10) {
+        Some(d as usize).filter(|n| *n <= ncap)
+    } else {
+        if !matches!(chars.next(), Some((_, '<'))) {
+            return Err(ParseError::InvalidBackRef(Span::new(pos - 1, chars.pos())));
+        }
+        let name = parse_group_name(chars, pos - 1)?;
+        names.get(&name).copied()
+    };
+
+    match n {
+        Some(n) => Ok(AST::BackRef(n, false)),
+        None => Err(ParseError::InvalidBackRef(Span::new(pos - 1, chars.pos()))),
+    }
+}
+
+/// 文字クラス中の1要素をパース
+///
+/// 通常の文字とエスケープされた文字はAST::Charに、
+/// [\d_]の\dのような定義済み文字クラスはAST::Classになる
+fn parse_class_item(chars: &mut ExprChars, start: usize) -> Result<AST, ParseError> {
+    match chars.next() {
+        Some((_, '\\')) => match chars.next() {
+            Some((i, c)) => parse_escape(chars, i, c),
+            None => Err(ParseError::UnclosedClass(Span::new(start, chars.pos()))),
+        },
+        Some((_, c)) => Ok(AST::Char(c)),
+        None => Err(ParseError::UnclosedClass(Span::new(start, chars.pos()))),
+    }
+}
+
+/// [a-z0-9_]や[^"]のような文字クラスをASTに変換
+///
+/// startは開き括弧[の位置で、charsは[の直後を指している。
+/// 先頭の]と、先頭か末尾の-は通常の文字として扱う
+///
+/// 例: []a]は]とaにマッチし、[a-]はaと-にマッチする
+fn parse_class(chars: &mut ExprChars, start: usize) -> Result<AST, ParseError> {
+    let mut class = Class {
+        ranges: Vec::new(),
+        negated: false,
+    };
+
+    if let Some((_, '^')) = chars.peek() {
+        chars.next();
+        class.negated = true;
+    }
+
+    let mut first = true;
+    loop {
+        let pos = match chars.peek() {
+            Some((_, ']')) if !first => {
+                chars.next();
+                return Ok(AST::Class(class));
+            }
+            Some((i, _)) => *i,
+            None => return Err(ParseError::UnclosedClass(Span::new(start, chars.pos()))),
+        };
+        first = false;
+
+        let c1 = match parse_class_item(chars, start)? {
+            AST::Char(c) => c,
+            AST::Class(c) => {
+                // 定義済み文字クラスは、マッチする範囲をそのまま追加
+                class.ranges.append(&mut c.to_ranges());
+                continue;
+            }
+            _ => unreachable!(), // parse_class_itemはCharかClassのみを返す
+        };
+
+        // 次が-で、その次が]でない場合は範囲指定
+        let mut ahead = chars.clone();
+        let is_range = matches!(ahead.next(), Some((_, '-')))
+            && !matches!(ahead.next(), Some((_, ']')) | None);
+        if is_range {
+            chars.next(); // -を読み飛ばす
+            let c2 = match parse_class_item(chars, start)? {
+                AST::Char(c) => c,
+                _ => {
+                    let span = Span::new(pos, chars.pos());
+                    return Err(ParseError::InvalidRange(span, c1, c1));
+                }
+            };
+            if c1 > c2 {
+                return Err(ParseError::InvalidRange(
+                    Span::new(pos, chars.pos()),
+                    c1,
+                    c2,
+                ));
+            }
+            class.ranges.push((c1, c2));
+        } else {
+            class.ranges.push((c1, c1));
         }
     }
 }
 
+/// 括弧の種類
+enum Group {
+    Capture(usize, Option<String>), // (...), (?P<name>...): キャプチャする括弧
+    NonCapture(Flags), // (?:...), (?i:...): キャプチャしない括弧。フラグは括弧内のみ有効
+    Flags(Flags),      // (?i): 閉じ括弧ではなく、以降のフラグを変更する
+    LookAhead(bool),   // (?=...), (?!...): 先読み。trueの場合は否定
+    LookBehind(bool),  // (?<=...), (?<!...): 後読み。trueの場合は否定
+}
+
+/// (?P<name>や(?<name>のグループ名をパース
+///
+/// posは開き括弧の位置で、charsは<の直後を指している。
+/// グループ名は英字かアンダースコアで始まり、英数字かアンダースコアが続くもの
+fn parse_group_name(chars: &mut ExprChars, pos: usize) -> Result<String, ParseError> {
+    let mut name = String::new();
+    loop {
+        match chars.next() {
+            Some((_, '>')) if !name.is_empty() => return Ok(name),
+            Some((_, c)) if c.is_ascii_alphabetic() || c == '_' => name.push(c),
+            Some((_, c)) if c.is_ascii_digit() && !name.is_empty() => name.push(c),
+            _ => return Err(ParseError::InvalidGroupName(Span::new(pos, chars.pos()))),
+        }
+    }
+}
+
+/// (?ims-ims)や(?ims-ims:のフラグをパース
+///
+/// posは開き括弧の位置で、charsは?の直後を指している。
+/// -より後のフラグは無効にする。flagsは現在のフラグ
+fn parse_flags(chars: &mut ExprChars, pos: usize, flags: &Flags) -> Result<Group, ParseError> {
+    let mut flags = *flags;
+    let mut negated = false;
+    let mut empty = true; // -の後にフラグがあるか
+    loop {
+        match chars.next() {
+            Some((_, 'i')) => flags.case_insensitive = !negated,
+            Some((_, 'm')) => flags.multi_line = !negated,
+            Some((_, 's')) => flags.dot_matches_new_line = !negated,
+            Some((_, '-')) if !negated => {
+                negated = true;
+                empty = true;
+                continue;
+            }
+            Some((_, ':')) if !(negated && empty) => return Ok(Group::NonCapture(flags)),
+            Some((_, ')')) if !empty => return Ok(Group::Flags(flags)),
+            _ => return Err(ParseError::InvalidGroup(Span::new(pos, chars.pos()))),
+        }
+        empty = false;
+    }
+}
+
+/// 開き括弧の直後をパースし、括弧の種類を返す
+///
+/// posは開き括弧の位置で、charsは開き括弧の直後を指している。
+/// キャプチャする括弧の場合は、ncapをインクリメントしてグループ番号とする。
+/// 先読みと後読みの括弧はキャプチャしない。
+/// flagsは現在のフラグ
+fn parse_group(
+    chars: &mut ExprChars,
+    pos: usize,
+    ncap: &mut usize,
+    names: &mut HashMap<String, usize>,
+    flags: &Flags,
+) -> Result<Group, ParseError> {
+    let name = if let Some((_, '?')) = chars.peek() {
+        chars.next();
+        if let Some((_, 'i' | 'm' | 's' | '-' | ':')) = chars.peek() {
+            return parse_flags(chars, pos, flags);
+        }
+        match chars.next() {
+            Some((_, '=')) => return Ok(Group::LookAhead(false)),
+            Some((_, '!')) => return Ok(Group::LookAhead(true)),
+            Some((_, 'P')) if matches!(chars.next(), Some((_, '<'))) => {
+                Some(parse_group_name(chars, pos)?)
+            }
+            Some((_, '<')) => match chars.peek() {
+                Some((_, '=')) => {
+                    chars.next();
+                    return Ok(Group::LookBehind(false));
+                }
+                Some((_, '!')) => {
+                    chars.next();
+                    return Ok(Group::LookBehind(true));
+                }
+                _ => Some(parse_group_name(chars, pos)?),
+            },
+            _ => return Err(ParseError::InvalidGroup(Span::new(pos, chars.pos()))),
+        }
+    } else {
+        None
+    };
+
+    *ncap += 1;
+    if let Some(name) = &name {
+        if names.insert(name.clone(), *ncap).is_some() {
+            let span = Span::new(pos, chars.pos());
+            return Err(ParseError::DuplicateGroupName(span, name.clone()));
+        }
+    }
+
+    Ok(Group::Capture(*ncap, name))
+}
+
+/// 直前にパースしたものの種類
+///
+/// 限量子を適用できるかの判定に用いる
+#[derive(PartialEq, Eq)]
+enum Prev {
+    Nothing,        // 式の先頭、開き括弧、|、(?i)の直後
+    Atom,           // 限量子で修飾できる式
+    Quantifier,     // 限量子
+    LazyQuantifier, // 非貪欲な限量子
+}
+
 /// parse_plust_star_question関数で利用するための列挙型
+#[allow(clippy::upper_case_acronyms)]
 enum PSQ {
     Plus,
     Star,
     Question,
+    Repeat(usize, Option<usize>), // {n,m}
 }
 
-/// +, *, ?をASTに変換
+/// {n}, {n,}, {n,m}の回数指定をパース
 ///
-/// 後置記法で、+, *, ?の前にパターンがない場合はエラー
+/// posは開き括弧{の位置で、charsは{の直後を指している。
+/// (最小回数, 最大回数)を返し、{n,}のように上限がない場合は最大回数をNoneとする
+fn parse_repeat(chars: &mut ExprChars, pos: usize) -> Result<PSQ, ParseError> {
+    // 10進数の数値を読み込む。数字が一つもない場合はNoneを返す
+    fn parse_num(chars: &mut ExprChars, pos: usize) -> Result<Option<usize>, ParseError> {
+        let mut num: Option<usize> = None;
+        while let Some((_, c)) = chars.peek() {
+            if let Some(d) = c.to_digit(10) {
+                let n = num.unwrap_or(0);
+                num = Some(
+                    n.checked_mul(10)
+                        .and_then(|n| n.checked_add(d as usize))
+                        .ok_or(ParseError::InvalidRepeat(Span::new(pos, chars.pos())))?,
+                );
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        Ok(num)
+    }
+
+    let min = parse_num(chars, pos)?
+        .ok_or_else(|| ParseError::InvalidRepeat(Span::new(pos, chars.pos())))?;
+    let max = match chars.next() {
+        Some((_, '}')) => Some(min),
+        Some((_, ',')) => {
+            let max = parse_num(chars, pos)?;
+            if !matches!(chars.next(), Some((_, '}'))) {
+                return Err(ParseError::InvalidRepeat(Span::new(pos, chars.pos())));
+            }
+            max
+        }
+        _ => return Err(ParseError::InvalidRepeat(Span::new(pos, chars.pos()))),
+    };
+
+    if let Some(max) = max {
+        if min > max {
+            let span = Span::new(pos, chars.pos());
+            return Err(ParseError::InvalidRepeatRange(span, min, max));
+        }
+    }
+
+    Ok(PSQ::Repeat(min, max))
+}
+
+/// +, *, ?, {n,m}をASTに変換
 ///
-/// 例: *ab, abc|+などはエラー
+/// 後置記法で、+, *, ?, {n,m}の前にパターンがない場合はエラー
+///
+/// 例: *ab, abc|+, {2}などはエラー
 fn parse_plus_start_question(
     seq: &mut Vec<AST>,
     ast_type: PSQ,
-    pos: usize,
+    span: Span,
 ) -> Result<(), ParseError> {
     if let Some(prev) = seq.pop() {
         // 正規表現の限量子は、直前に現れたパターンを現量するため、seqの最後尾から一つポップする
         let ast = match ast_type {
             // 直前のパターンを取得できた場合、ast_typeで示された限量子に基づいて、適当なASTを生成する
-            PSQ::Plus => AST::Plus(Box::new(prev)),
-            PSQ::Star => AST::Star(Box::new(prev)),
-            PSQ::Question => AST::Question(Box::new(prev)),
+            PSQ::Plus => AST::Plus(Box::new(prev), true),
+            PSQ::Star => AST::Star(Box::new(prev), true),
+            PSQ::Question => AST::Question(Box::new(prev), true),
+            PSQ::Repeat(min, max) => AST::Repeat(Box::new(prev), min, max, true),
         };
         seq.push(ast);
         Ok(())
     } else {
-        Err(ParseError::NoPrev(pos))
+        Err(ParseError::NoPrev(span))
+    }
+}
+
+/// 直前の限量子を非貪欲にする
+///
+/// e+?, e*?, e??, e{n,m}?のように、限量子の直後に?がある場合に呼び出す
+fn make_lazy(seq: &mut [AST]) {
+    if let Some(
+        AST::Plus(_, greedy)
+        | AST::Star(_, greedy)
+        | AST::Question(_, greedy)
+        | AST::Repeat(_, _, _, greedy),
+    ) = seq.last_mut()
+    {
+        *greedy = false;
     }
 }
 
@@ -114,6 +1158,36 @@ fn fold_or(mut seq_or: Vec<AST>) -> Option<AST> {
 
 /// 正規表現を抽象構文木に変換
 pub fn parse(expr: &str) -> Result<AST, DynError> {
+    parse_with_flags(expr, Flags::default())
+}
+
+/// フラグに応じて、文字、文字クラス、., ^, $のASTを変換
+fn apply_flags(ast: AST, flags: &Flags) -> AST {
+    match ast {
+        AST::Char(c) if flags.case_insensitive => {
+            let variants = case_variants(c);
+            if variants.is_empty() {
+                AST::Char(c)
+            } else {
+                let mut ranges = vec![(c, c)];
+                ranges.extend(variants.into_iter().map(|v| (v, v)));
+                AST::Class(Class {
+                    ranges,
+                    negated: false,
+                })
+            }
+        }
+        AST::Class(class) if flags.case_insensitive => AST::Class(class.case_fold()),
+        AST::BackRef(n, _) if flags.case_insensitive => AST::BackRef(n, true),
+        AST::Any if flags.dot_matches_new_line => AST::AnyNewline,
+        AST::Assert(Assertion::Begin) if flags.multi_line => AST::Assert(Assertion::LineBegin),
+        AST::Assert(Assertion::End) if flags.multi_line => AST::Assert(Assertion::LineEnd),
+        ast => ast,
+    }
+}
+
+/// 正規表現をフラグを指定してパースし、抽象構文木に変換
+pub fn parse_with_flags(expr: &str, mut flags: Flags) -> Result<AST, DynError> {
     // 内部状態を表現するための型
     // Char状態: 文字列処理中
     // Escapse状態: エスケープシーケンス処理中
@@ -126,67 +1200,170 @@ pub fn parse(expr: &str) -> Result<AST, DynError> {
     let mut seq_or = Vec::new(); // 現在のOrのコンテキスト
     let mut stack = Vec::new(); // コンテキストのスタック
     let mut state = ParseState::Char; // 現在の状態
+    let mut ncap = 0; // キャプチャグループの数
+    let mut names = HashMap::new(); // グループ名からグループ番号への対応
+    let mut prev = Prev::Nothing; // 直前にパースしたもの
 
-    for (i, c) in expr.chars().enumerate() {
+    let mut chars = ExprChars::new(expr);
+    while let Some((i, c)) = chars.next() {
         match &state {
             ParseState::Char => {
+                // 限量子の直後の?は、その限量子を非貪欲にする
+                if c == '?' && prev == Prev::Quantifier {
+                    make_lazy(&mut seq);
+                    prev = Prev::LazyQuantifier;
+                    continue;
+                }
+
+                let psq = match c {
+                    '+' => Some(PSQ::Plus),
+                    '*' => Some(PSQ::Star),
+                    '?' => Some(PSQ::Question),
+                    '{' => Some(parse_repeat(&mut chars, i)?),
+                    _ => None,
+                };
+                if let Some(psq) = psq {
+                    let span = Span::new(i, chars.pos());
+                    match prev {
+                        Prev::Atom => parse_plus_start_question(&mut seq, psq, span)?,
+                        // a**やa+?*のように、限量子を限量子で修飾している場合はエラー
+                        Prev::Quantifier | Prev::LazyQuantifier => {
+                            return Err(Box::new(ParseError::NestedQuantifier(span)))
+                        }
+                        // (?i)*や(?:)*のように、修飾する式がない場合はエラー
+                        Prev::Nothing => return Err(Box::new(ParseError::NoPrev(span))),
+                    }
+                    prev = Prev::Quantifier;
+                    continue;
+                }
+
+                // 以降で式をseqに追加した場合は、Prev::Atomとする
+                prev = Prev::Atom;
                 match c {
-                    '+' => parse_plus_start_question(&mut seq, PSQ::Plus, i)?,
-                    '*' => parse_plus_start_question(&mut seq, PSQ::Star, i)?,
-                    '?' => parse_plus_start_question(&mut seq, PSQ::Question, i)?,
                     '(' => {
-                        // 現在のコンテキストをスタックに保存し、
+                        prev = Prev::Nothing;
+                        let group = parse_group(&mut chars, i, &mut ncap, &mut names, &flags)?;
+                        if let Group::Flags(f) = group {
+                            // (?i)の場合は、括弧の外側のフラグを変更する
+                            flags = f;
+                            continue;
+                        }
+
+                        // 現在のコンテキスト、括弧の種類、フラグ、開き括弧の位置をスタックに保存し、
                         // 現在のコンテキストを空の状態にする
-                        let prev = take(&mut seq);
+                        let prev_seq = take(&mut seq);
                         let prev_or = take(&mut seq_or);
-                        stack.push((prev, prev_or));
+                        stack.push((prev_seq, prev_or, group, flags, i));
+                        if let Some((_, _, Group::NonCapture(f), _, _)) = stack.last() {
+                            flags = *f;
+                        }
                     }
                     ')' => {
                         // 現在のコンテキストをスタックからポップ
-                        if let Some((mut prev, prev_or)) = stack.pop() {
+                        if let Some((mut prev_seq, prev_or, group, prev_flags, pos)) = stack.pop() {
                             // "()"のように、式が空の場合はpushしない
                             if !seq.is_empty() {
                                 seq_or.push(AST::Seq(seq));
                             }
 
                             // Orを生成
-                            if let Some(ast) = fold_or(seq_or) {
-                                prev.push(ast);
+                            let ast = fold_or(seq_or);
+                            match group {
+                                Group::Capture(n, name) => {
+                                    // 式が空の場合も、空文字列をキャプチャする
+                                    let ast = ast.unwrap_or(AST::Seq(Vec::new()));
+                                    prev_seq.push(AST::Capture(n, name, Box::new(ast)));
+                                }
+                                Group::NonCapture(_) => {
+                                    if let Some(ast) = ast {
+                                        prev_seq.push(ast);
+                                    } else {
+                                        // (?:)のように式が空の場合は、限量子で修飾できない
+                                        prev = Prev::Nothing;
+                                    }
+                                }
+                                Group::LookAhead(negative) => {
+                                    let ast = ast.unwrap_or(AST::Seq(Vec::new()));
+                                    let look = if negative {
+                                        Look::NegativeAhead
+                                    } else {
+                                        Look::Ahead
+                                    };
+                                    prev_seq.push(AST::Look(look, Box::new(ast)));
+                                }
+                                Group::LookBehind(negative) => {
+                                    // 後読みは、マッチする文字数に上限がある式のみとする
+                                    let ast = ast.unwrap_or(AST::Seq(Vec::new()));
+                                    let (min, max) = match ast.match_len() {
+                                        (min, Some(max)) => (min, max),
+                                        (_, None) => {
+                                            let span = Span::new(pos, i + 1);
+                                            return Err(Box::new(ParseError::UnboundedLookBehind(
+                                                span,
+                                            )));
+                                        }
+                                    };
+                                    let look = if negative {
+                                        Look::NegativeBehind(min, max)
+                                    } else {
+                                        Look::Behind(min, max)
+                                    };
+                                    prev_seq.push(AST::Look(look, Box::new(ast)));
+                                }
+                                Group::Flags(_) => unreachable!(), // (?i)はスタックに積まない
                             }
 
                             // 以前のコンテキストを、現在のコンテキストにする
-                            seq = prev;
+                            seq = prev_seq;
                             seq_or = prev_or;
+                            flags = prev_flags;
                         } else {
                             // "abc)"のように、開き括弧がないのに閉じ括弧がある場合はエラー
-                            return Err(Box::new(ParseError::InvalidRightParen(i)));
+                            let span = Span::new(i, i + 1);
+                            return Err(Box::new(ParseError::InvalidRightParen(span)));
                         }
                     }
                     '|' => {
+                        prev = Prev::Nothing;
                         if seq.is_empty() {
                             // "||", "(|abc)"などと、式がからの場合はエラー
-                            return Err(Box::new(ParseError::NoPrev(i)));
+                            return Err(Box::new(ParseError::NoPrev(Span::new(i, i + 1))));
                         } else {
                             let prev = take(&mut seq);
                             seq_or.push(AST::Seq(prev));
                         }
                     }
+                    '[' => seq.push(apply_flags(parse_class(&mut chars, i)?, &flags)),
+                    '.' => seq.push(apply_flags(AST::Any, &flags)),
+                    '^' => seq.push(apply_flags(AST::Assert(Assertion::Begin), &flags)),
+                    '$' => seq.push(apply_flags(AST::Assert(Assertion::End), &flags)),
                     '\\' => state = ParseState::Escapse,
-                    _ => seq.push(AST::Char(c)),
+                    _ => seq.push(apply_flags(AST::Char(c), &flags)),
                 }
             }
             ParseState::Escapse => {
                 // エスケープシーケンス処理
-                let ast = parse_escape(i, c)?;
-                seq.push(ast);
+                let ast = match c {
+                    '1'..='9' | 'k' => parse_backref(&mut chars, i, c, ncap, &names)?,
+                    'b' => AST::Assert(Assertion::WordBoundary),
+                    'B' => AST::Assert(Assertion::NotWordBoundary),
+                    _ => parse_escape(&mut chars, i, c)?,
+                };
+                seq.push(apply_flags(ast, &flags));
                 state = ParseState::Char;
             }
         }
     }
 
-    // 閉じ括弧が足りない場合はエラー
-    if !stack.is_empty() {
-        return Err(Box::new(ParseError::NoRightParen));
+    // abc\のように、エスケープする文字がない場合はエラー
+    if let ParseState::Escapse = state {
+        let span = Span::new(chars.len - 1, chars.len);
+        return Err(Box::new(ParseError::IncompleteEscape(span)));
+    }
+
+    // 閉じ括弧が足りない場合はエラー。最後に開いた括弧の位置を範囲とする
+    if let Some((_, _, _, _, pos)) = stack.pop() {
+        return Err(Box::new(ParseError::NoRightParen(Span::new(pos, pos + 1))));
     }
 
     // "()"のように、式がからの場合はpushしない
@@ -198,6 +1375,6 @@ pub fn parse(expr: &str) -> Result<AST, DynError> {
     if let Some(ast) = fold_or(seq_or) {
         Ok(ast)
     } else {
-        Err(Box::new(ParseError::Empty))
+        Err(Box::new(ParseError::Empty(Span::new(0, chars.len))))
     }
 }
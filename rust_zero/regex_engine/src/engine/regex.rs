@@ -0,0 +1,680 @@
+//! コンパイル済みの正規表現
+use super::{
+    codegen,
+    dfa::LazyDfa,
+    evaluator::{self, EvalConfig, EvalError},
+    optimizer,
+    parser::{self, Flags, AST},
+    text::{self, Text},
+    Instruction,
+};
+use crate::helper::DynError;
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+/// 遅延DFAでキャッシュする状態数のデフォルトの上限
+const DFA_STATE_LIMIT: usize = 4096;
+
+/// 正規表現をコンパイルするためのビルダー
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::engine::RegexBuilder;
+/// let re = RegexBuilder::new("a(b|c)+").depth_first(true).build().unwrap();
+/// assert!(re.is_match("xabcb").unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    expr: String,
+    is_depth: bool,
+    lazy_dfa: bool,
+    dfa_state_limit: usize,
+    config: EvalConfig,
+    flags: Flags,
+    optimize: bool,
+}
+
+impl RegexBuilder {
+    /// 正規表現exprのビルダーを生成
+    ///
+    /// デフォルトでは、線形時間で評価できる幅優先探索を用いる
+    pub fn new(expr: &str) -> Self {
+        RegexBuilder {
+            expr: expr.to_string(),
+            is_depth: false,
+            lazy_dfa: false,
+            dfa_state_limit: DFA_STATE_LIMIT,
+            config: EvalConfig::default(),
+            flags: Flags::default(),
+            optimize: true,
+        }
+    }
+
+    /// trueの場合は深さ優先探索を、falseの場合は幅優先探索を用いて評価する
+    pub fn depth_first(&mut self, yes: bool) -> &mut Self {
+        self.is_depth = yes;
+        self
+    }
+
+    /// trueの場合は、大文字と小文字を区別せずにマッチングを行う
+    ///
+    /// Unicodeの単純ケースフォールディングで同じ文字となるものを同一視する。
+    /// 正規表現中で(?i)と指定した場合と同じ
+    ///
+    /// ```
+    /// use regex_engine::engine::RegexBuilder;
+    /// let re = RegexBuilder::new("hello").case_insensitive(true).build().unwrap();
+    /// assert!(re.is_match("Say HELLO").unwrap());
+    /// ```
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.flags.case_insensitive = yes;
+        self
+    }
+
+    /// trueの場合は、^と$を文字列の先頭と末尾に加えて、行頭と行末にもマッチさせる
+    ///
+    /// 正規表現中で(?m)と指定した場合と同じ
+    ///
+    /// ```
+    /// use regex_engine::engine::RegexBuilder;
+    /// let re = RegexBuilder::new("^b$").multi_line(true).build().unwrap();
+    /// assert!(re.is_match("a\nb\nc").unwrap());
+    /// ```
+    pub fn multi_line(&mut self, yes: bool) -> &mut Self {
+        self.flags.multi_line = yes;
+        self
+    }
+
+    /// trueの場合は、.を改行にもマッチさせる
+    ///
+    /// 正規表現中で(?s)と指定した場合と同じ
+    pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut Self {
+        self.flags.dot_matches_new_line = yes;
+        self
+    }
+
+    /// 深さ優先探索で実行する命令数の上限を設定。Noneの場合は上限なし（デフォルト）
+    ///
+    /// 上限を超えた場合は、評価時にEvalError::StepLimitExceededを返す
+    ///
+    /// ```
+    /// use regex_engine::engine::RegexBuilder;
+    /// let re = RegexBuilder::new("^(a|aa)*b")
+    ///     .depth_first(true)
+    ///     .step_limit(Some(10_000))
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(re.is_match(&"a".repeat(30)).is_err());
+    /// ```
+    pub fn step_limit(&mut self, limit: Option<usize>) -> &mut Self {
+        self.config.step_limit = limit;
+        self
+    }
+
+    /// 深さ優先探索でバックトラックに備えて保持する分岐の数の上限を設定。Noneの場合は上限なし（デフォルト）
+    ///
+    /// 上限を超えた場合は、評価時にEvalError::DepthLimitExceededを返す
+    pub fn depth_limit(&mut self, limit: Option<usize>) -> &mut Self {
+        self.config.depth_limit = limit;
+        self
+    }
+
+    /// trueの場合は、深さ優先探索で訪れた(pc, sp)を記録して再訪しないようにする
+    ///
+    /// 命令数 × 文字列長のメモリを用いる代わりに、
+    /// 深さ優先探索でも命令数 × 文字列長に比例する時間で評価できる
+    pub fn memoize(&mut self, yes: bool) -> &mut Self {
+        self.config.memoize = yes;
+        self
+    }
+
+    /// trueの場合は、生成した命令列を最適化する（デフォルト）
+    ///
+    /// 最適化の有無によらず、マッチ結果は変わらない。
+    /// 最適化する場合は、マッチが必ず始まる文字列を求め、評価前に文字列探索で候補位置を絞り込む
+    pub fn optimize(&mut self, yes: bool) -> &mut Self {
+        self.optimize = yes;
+        self
+    }
+
+    /// trueの場合は、is_matchの評価に遅延DFAを用いる
+    ///
+    /// find、capturesなどの範囲を求める評価や、
+    /// 状態数が上限を超えた場合は、深さ優先探索または幅優先探索で評価する
+    pub fn lazy_dfa(&mut self, yes: bool) -> &mut Self {
+        self.lazy_dfa = yes;
+        self
+    }
+
+    /// 遅延DFAでキャッシュする状態数の上限を設定
+    ///
+    /// 上限を超えた場合はキャッシュを破棄し、その評価は深さ優先探索または幅優先探索で行う
+    pub fn dfa_state_limit(&mut self, limit: usize) -> &mut Self {
+        self.dfa_state_limit = limit;
+        self
+    }
+
+    /// 正規表現をパースしてコードを生成し、Regexを返す
+    ///
+    /// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合はErrを返す。
+    /// \1や\k<name>の後方参照は深さ優先探索でのみ評価できるため、
+    /// 幅優先探索を用いる場合はErrを返す
+    ///
+    /// ```
+    /// use regex_engine::engine::RegexBuilder;
+    /// let re = RegexBuilder::new("(\\w+) \\1").depth_first(true).build().unwrap();
+    /// assert_eq!(re.find("it is is ok").unwrap().unwrap().as_str(), "is is");
+    /// assert!(RegexBuilder::new("(\\w+) \\1").build().is_err());
+    /// ```
+    pub fn build(&self) -> Result<Regex, DynError> {
+        let ast = parser::parse_with_flags(&self.expr, self.flags)?;
+        let mut code = codegen::get_search_code(&ast)?;
+        if !self.is_depth && evaluator::backref_slots(&code) > 0 {
+            return Err(Box::new(EvalError::BackRefNotSupported));
+        }
+        let mut prefix = None;
+        if self.optimize {
+            code = optimizer::optimize(code)?;
+            let p = optimizer::literal_prefix(&codegen::get_code(&ast)?);
+            if !p.is_empty() {
+                prefix = Some(p);
+            }
+        }
+        let names = Arc::new(ast.capture_names());
+        let dfa = if self.lazy_dfa {
+            LazyDfa::new(&code, self.dfa_state_limit).map(Mutex::new)
+        } else {
+            None
+        };
+        Ok(Regex {
+            expr: self.expr.clone(),
+            ast,
+            code,
+            names,
+            is_depth: self.is_depth,
+            config: self.config,
+            prefix,
+            dfa,
+        })
+    }
+}
+
+/// コンパイル済みの正規表現
+///
+/// パースとコード生成は生成時に1度だけ行い、
+/// 以降は同じ命令列を使い回してマッチングを行う
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::engine::Regex;
+/// let re = Regex::new("[0-9]+").unwrap();
+/// let m = re.find("abc 123 def").unwrap().unwrap();
+/// assert_eq!(m.as_str(), "123");
+/// assert_eq!(m.range(), 4..7);
+///
+/// let re = Regex::new("(?P<year>[0-9]+)-([0-9]+)").unwrap();
+/// let caps = re.captures("date: 2024-01").unwrap().unwrap();
+/// assert_eq!(caps.name("year").unwrap().as_str(), "2024");
+/// assert_eq!(caps.get(2).unwrap().as_str(), "01");
+/// ```
+#[derive(Debug)]
+pub struct Regex {
+    expr: String,
+    pub(super) ast: AST,
+    pub(super) code: Vec<Instruction>,
+    names: Arc<Vec<Option<String>>>, // キャプチャグループの名前
+    is_depth: bool,
+    config: EvalConfig,
+    pub(super) prefix: Option<String>, // マッチが必ず始まる文字列
+    dfa: Option<Mutex<LazyDfa>>,       // is_matchで用いる遅延DFA
+}
+
+impl Regex {
+    /// 正規表現をコンパイルする
+    ///
+    /// 評価には幅優先探索を用いる。深さ優先探索を用いる場合はRegexBuilderを利用する
+    pub fn new(expr: &str) -> Result<Regex, DynError> {
+        RegexBuilder::new(expr).build()
+    }
+
+    /// コンパイル元の正規表現を返す
+    pub fn as_str(&self) -> &str {
+        &self.expr
+    }
+
+    /// マッチ全体を含む、キャプチャグループの数を返す
+    pub fn captures_len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// キャプチャグループの名前を、グループ番号順に返す
+    ///
+    /// 0番目はマッチ全体を表すため常にNoneとなり、名前のないグループもNoneとなる
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.names.iter().map(|name| name.as_deref())
+    }
+
+    /// textの中に正規表現にマッチする部分文字列があるかを判定
+    ///
+    /// 遅延DFAが有効な場合は遅延DFAで評価し、
+    /// 状態数が上限を超えた場合や、他のスレッドが遅延DFAを利用中の場合は通常の評価器で評価する
+    pub fn is_match(&self, text: &str) -> Result<bool, DynError> {
+        self.is_match_bytes(text.as_bytes())
+    }
+
+    /// バイト列bytesの中に正規表現にマッチする部分があるかを判定
+    ///
+    /// bytesはUTF-8としてデコードしながら評価する。
+    /// UTF-8として不正なバイトは、1バイトずつU+FFFDとみなす
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let re = Regex::new("ab+c").unwrap();
+    /// assert!(re.is_match_bytes(b"\xff\xfeabbc").unwrap());
+    /// ```
+    pub fn is_match_bytes(&self, bytes: &[u8]) -> Result<bool, DynError> {
+        // マッチはプレフィックスの位置からしか始まらないため、それより前からは評価しない
+        let start = match &self.prefix {
+            Some(prefix) => match text::find(bytes, prefix.as_bytes()) {
+                Some(start) => start,
+                None => return Ok(false),
+            },
+            None => 0,
+        };
+
+        // 遅延DFAは後読みを扱わないため、プレフィックスより前の文字は
+        // プレフィックスの後のアサーションの判定に影響しない
+        if let Some(dfa) = &self.dfa {
+            if let Ok(mut dfa) = dfa.try_lock() {
+                if let Some(result) = dfa.is_match(&self.code, text::chars(&bytes[start..]))? {
+                    return Ok(result);
+                }
+            }
+        }
+
+        let slots =
+            evaluator::eval_slots(&self.code, bytes, start, 0, self.is_depth, &self.config)?;
+        Ok(slots.is_some())
+    }
+
+    /// textの中で、最も左から始まるマッチを返す
+    ///
+    /// 同じ位置から始まるマッチが複数ある場合は、深さ優先探索で最初に見つかるものを返す（leftmost-first）。
+    /// すなわち、e1|e2はe1を優先し、貪欲な繰り返しはできるだけ多く、
+    /// 非貪欲な繰り返し（e*?, e+?, e??, e{n,m}?）はできるだけ少なく繰り返したものとなる。
+    /// 幅優先探索を用いた場合も、同じマッチを返す
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let re = Regex::new("<.+>").unwrap();
+    /// assert_eq!(re.find("<a><b>").unwrap().unwrap().as_str(), "<a><b>");
+    /// let re = Regex::new("<.+?>").unwrap();
+    /// assert_eq!(re.find("<a><b>").unwrap().unwrap().as_str(), "<a>");
+    /// let re = Regex::new("a|ab").unwrap();
+    /// assert_eq!(re.find("ab").unwrap().unwrap().as_str(), "a");
+    /// ```
+    pub fn find<'t>(&self, text: &'t str) -> Result<Option<Match<'t>>, DynError> {
+        let slots = self.slots_at(&text, 0, 2)?;
+        Ok(slots.map(|slots| Match::from_slots(text, &slots)))
+    }
+
+    /// textの中で、重なり合わないマッチを左から順に返すイテレータを生成
+    ///
+    /// 直前のマッチの終了位置と同じ位置では、長さ0のマッチを返さない
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        Matches(Locations::new(self, text, 2))
+    }
+
+    /// textの中で、最も左から始まるマッチと、各キャプチャグループの範囲を返す
+    ///
+    /// captures.get(0)がマッチ全体となる。
+    /// 深さ優先探索と幅優先探索のどちらでも、同じ範囲が得られる
+    pub fn captures<'t>(&self, text: &'t str) -> Result<Option<Captures<'t>>, DynError> {
+        let slots = self.slots_at(&text, 0, self.names.len() * 2)?;
+        Ok(slots.map(|slots| Captures::new(text, slots, &self.names)))
+    }
+
+    /// textの中で、重なり合わないマッチと各キャプチャグループの範囲を、左から順に返すイテレータを生成
+    ///
+    /// マッチする位置はfind_iterと同じ
+    pub fn captures_iter<'r, 't>(&'r self, text: &'t str) -> CaptureMatches<'r, 't> {
+        CaptureMatches(Locations::new(self, text, self.names.len() * 2))
+    }
+
+    /// バイト列bytesの中で、最も左から始まるマッチを返す
+    ///
+    /// マッチの範囲はバイト単位となる。不正なバイトの扱いはis_match_bytesと同じ
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let m = re.find_bytes(b"\x80abc 123").unwrap().unwrap();
+    /// assert_eq!(m.as_bytes(), b"123");
+    /// assert_eq!(m.range(), 5..8);
+    /// ```
+    pub fn find_bytes<'t>(&self, bytes: &'t [u8]) -> Result<Option<ByteMatch<'t>>, DynError> {
+        let slots = self.slots_at(&bytes, 0, 2)?;
+        Ok(slots.map(|slots| ByteMatch::new(bytes, &slots)))
+    }
+
+    /// バイト列bytesの中で、重なり合わないマッチを左から順に返すイテレータを生成
+    ///
+    /// マッチする位置はfind_iterと同じで、長さ0のマッチの次は1文字（不正なバイトの場合は1バイト）進める
+    pub fn find_iter_bytes<'r, 't>(&'r self, bytes: &'t [u8]) -> ByteMatches<'r, 't> {
+        ByteMatches(Locations::new(self, bytes, 2))
+    }
+
+    /// 位置spから探索を開始し、最も左から始まるマッチのスロットを返す
+    ///
+    /// nslotsは用意するスロットの数で、少なくとも2つ（マッチ全体）を用意する
+    fn slots_at<H: Haystack>(
+        &self,
+        input: &H,
+        sp: usize,
+        nslots: usize,
+    ) -> Result<Option<Vec<Option<usize>>>, DynError> {
+        // プレフィックスが現れる位置から評価を開始
+        let sp = match &self.prefix {
+            Some(prefix) => match input.find_prefix(sp, prefix) {
+                Some(sp) => sp,
+                None => return Ok(None),
+            },
+            None => sp,
+        };
+
+        evaluator::eval_slots(
+            &self.code,
+            input.text(),
+            sp,
+            nslots,
+            self.is_depth,
+            &self.config,
+        )
+    }
+}
+
+/// 探索対象の文字列
+///
+/// &strと&[u8]を同様に扱うためのトレイト。どちらもUTF-8のバイト列のまま、バイト単位で評価する
+trait Haystack {
+    type Text: Text + ?Sized;
+
+    /// 評価器に与える入力文字列
+    fn text(&self) -> &Self::Text;
+
+    /// 位置sp以降でprefixが最初に現れる位置を返す
+    fn find_prefix(&self, sp: usize, prefix: &str) -> Option<usize>;
+}
+
+impl Haystack for &str {
+    type Text = [u8];
+
+    fn text(&self) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn find_prefix(&self, sp: usize, prefix: &str) -> Option<usize> {
+        // 評価器は文字の境界にのみ位置を進めるため、spは常に文字の境界となる
+        let i = self[sp..].find(prefix)?;
+        Some(sp + i)
+    }
+}
+
+impl Haystack for &[u8] {
+    type Text = [u8];
+
+    fn text(&self) -> &[u8] {
+        self
+    }
+
+    fn find_prefix(&self, sp: usize, prefix: &str) -> Option<usize> {
+        let i = text::find(&self[sp..], prefix.as_bytes())?;
+        Some(sp + i)
+    }
+}
+
+/// マッチした部分文字列
+///
+/// 位置はバイト単位と文字単位の両方で取得できる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'t> {
+    text: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    /// スロットの0番目と1番目から、マッチ全体を生成
+    fn from_slots(text: &'t str, slots: &[Option<usize>]) -> Self {
+        match slots {
+            [Some(start), Some(end), ..] => Match {
+                text,
+                start: *start,
+                end: *end,
+            },
+            // マッチした場合は、Save(0)とSave(1)を必ず実行する
+            _ => unreachable!(),
+        }
+    }
+
+    /// マッチの開始位置（バイト単位）
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// マッチの終了位置（バイト単位）
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// マッチした範囲（バイト単位）
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// マッチした範囲（文字単位）
+    ///
+    /// 評価はバイト単位で行うため、呼び出した時点で先頭からの文字数を数える
+    pub fn char_range(&self) -> Range<usize> {
+        let start = self.text[..self.start].chars().count();
+        start..start + self.as_str().chars().count()
+    }
+
+    /// マッチした部分文字列
+    pub fn as_str(&self) -> &'t str {
+        &self.text[self.range()]
+    }
+}
+
+/// マッチ全体と、各キャプチャグループにマッチした範囲
+///
+/// locs[2i]とlocs[2i + 1]がi番目のグループの開始位置と終了位置（バイト単位）となる
+#[derive(Debug, Clone)]
+pub struct Captures<'t> {
+    text: &'t str,
+    locs: Vec<Option<usize>>,
+    names: Arc<Vec<Option<String>>>,
+}
+
+impl<'t> Captures<'t> {
+    fn new(text: &'t str, locs: Vec<Option<usize>>, names: &Arc<Vec<Option<String>>>) -> Self {
+        Captures {
+            text,
+            locs,
+            names: names.clone(),
+        }
+    }
+
+    /// i番目のグループにマッチした範囲を返す。0番目はマッチ全体
+    ///
+    /// グループがマッチに関与しなかった場合はNoneを返す
+    pub fn get(&self, i: usize) -> Option<Match<'t>> {
+        match (self.locs.get(i * 2), self.locs.get(i * 2 + 1)) {
+            (Some(Some(start)), Some(Some(end))) => Some(Match {
+                text: self.text,
+                start: *start,
+                end: *end,
+            }),
+            _ => None,
+        }
+    }
+
+    /// 名前付きグループにマッチした範囲を返す
+    pub fn name(&self, name: &str) -> Option<Match<'t>> {
+        let i = self.names.iter().position(|n| n.as_deref() == Some(name))?;
+        self.get(i)
+    }
+
+    /// マッチ全体を含む、グループの数を返す
+    pub fn len(&self) -> usize {
+        self.locs.len() / 2
+    }
+
+    /// グループが一つもない場合にtrueを返す
+    pub fn is_empty(&self) -> bool {
+        self.locs.is_empty()
+    }
+}
+
+/// バイト列中のマッチした部分
+///
+/// 位置はバイト単位となる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteMatch<'t> {
+    bytes: &'t [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'t> ByteMatch<'t> {
+    /// スロットの0番目と1番目から、マッチ全体を生成
+    fn new(bytes: &'t [u8], slots: &[Option<usize>]) -> Self {
+        match slots {
+            [Some(start), Some(end), ..] => ByteMatch {
+                bytes,
+                start: *start,
+                end: *end,
+            },
+            // マッチした場合は、Save(0)とSave(1)を必ず実行する
+            _ => unreachable!(),
+        }
+    }
+
+    /// マッチの開始位置
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// マッチの終了位置
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// マッチした範囲
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// マッチした部分のバイト列
+    pub fn as_bytes(&self) -> &'t [u8] {
+        &self.bytes[self.range()]
+    }
+}
+
+/// 重なり合わないマッチのスロットを、左から順に求める
+///
+/// 直前のマッチの終了位置と同じ位置では、長さ0のマッチを返さない
+struct Locations<'r, H> {
+    regex: &'r Regex,
+    input: H,
+    nslots: usize,
+    last_end: usize,           // 次に探索を開始する位置
+    last_match: Option<usize>, // 直前のマッチの終了位置
+}
+
+impl<'r, H: Haystack> Locations<'r, H> {
+    fn new(regex: &'r Regex, input: H, nslots: usize) -> Self {
+        Locations {
+            regex,
+            input,
+            nslots,
+            last_end: 0,
+            last_match: None,
+        }
+    }
+
+    fn next_slots(&mut self) -> Option<Result<Vec<Option<usize>>, DynError>> {
+        let len = self.input.text().len();
+        loop {
+            if self.last_end > len {
+                return None;
+            }
+
+            let slots = match self.regex.slots_at(&self.input, self.last_end, self.nslots) {
+                Ok(Some(slots)) => slots,
+                Ok(None) => return None,
+                Err(e) => {
+                    // エラー後は探索を打ち切る
+                    self.last_end = len + 1;
+                    return Some(Err(e));
+                }
+            };
+
+            let (start, end) = match slots[..] {
+                [Some(start), Some(end), ..] => (start, end),
+                _ => unreachable!(), // マッチした場合は、Save(0)とSave(1)を必ず実行する
+            };
+            if start == end {
+                // 長さ0のマッチの場合は、無限ループしないよう次の文字から探索する
+                self.last_end = self.input.text().next_pos(end);
+                if self.last_match == Some(end) {
+                    continue;
+                }
+            } else {
+                self.last_end = end;
+            }
+            self.last_match = Some(end);
+            return Some(Ok(slots));
+        }
+    }
+}
+
+/// Regex::find_iterが返すイテレータ
+pub struct Matches<'r, 't>(Locations<'r, &'t str>);
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = Result<Match<'t>, DynError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slots = self.0.next_slots()?;
+        Some(slots.map(|slots| Match::from_slots(self.0.input, &slots)))
+    }
+}
+
+/// Regex::captures_iterが返すイテレータ
+pub struct CaptureMatches<'r, 't>(Locations<'r, &'t str>);
+
+impl<'r, 't> Iterator for CaptureMatches<'r, 't> {
+    type Item = Result<Captures<'t>, DynError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slots = self.0.next_slots()?;
+        Some(slots.map(|slots| Captures::new(self.0.input, slots, &self.0.regex.names)))
+    }
+}
+
+/// Regex::find_iter_bytesが返すイテレータ
+pub struct ByteMatches<'r, 't>(Locations<'r, &'t [u8]>);
+
+impl<'r, 't> Iterator for ByteMatches<'r, 't> {
+    type Item = Result<ByteMatch<'t>, DynError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slots = self.0.next_slots()?;
+        Some(slots.map(|slots| ByteMatch::new(self.0.input, &slots)))
+    }
+}
@@ -0,0 +1,288 @@
+//! 正規表現による置換と分割
+use super::regex::{Captures, Matches, Regex};
+use crate::helper::DynError;
+use std::borrow::Cow;
+
+/// マッチした部分を置き換える文字列を生成するためのトレイト
+///
+/// &strとStringは、$1や${name}を展開するテンプレートとなる（Captures::expandを参照）。
+/// FnMut(&Captures) -> Tのクロージャは、その返り値で置き換える。
+/// テンプレートを展開せずにそのまま置き換える場合はNoExpandを用いる
+pub trait Replacer {
+    /// capsで表されるマッチを置き換える文字列を、dstの末尾に追加
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String);
+
+    /// マッチによらず同じ文字列で置き換える場合は、その文字列を返す
+    ///
+    /// Someを返す場合は、キャプチャグループの範囲を求めずに置換する
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        None
+    }
+}
+
+impl Replacer for &str {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        if self.contains('$') {
+            None
+        } else {
+            Some(Cow::Borrowed(self))
+        }
+    }
+}
+
+impl Replacer for &String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        self.as_str().replace_append(caps, dst)
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        if self.contains('$') {
+            None
+        } else {
+            Some(Cow::Borrowed(self.as_str()))
+        }
+    }
+}
+
+impl Replacer for String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        (&*self).replace_append(caps, dst)
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        (&*self).no_expansion().map(|s| Cow::Owned(s.into_owned()))
+    }
+}
+
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures<'_>) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self(caps).as_ref());
+    }
+}
+
+/// $1や${name}を展開せずに、そのままの文字列で置き換えるためのReplacer
+///
+/// ```
+/// use regex_engine::engine::{NoExpand, Regex};
+/// let re = Regex::new("[0-9]+").unwrap();
+/// assert_eq!(re.replace_all("a1b22", NoExpand("$1")).unwrap(), "a$1b$1");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct NoExpand<'s>(pub &'s str);
+
+impl Replacer for NoExpand<'_> {
+    fn replace_append(&mut self, _: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self.0);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed(self.0))
+    }
+}
+
+impl<'t> Captures<'t> {
+    /// テンプレートtemplate中の参照を、キャプチャグループにマッチした文字列に置き換えて、
+    /// dstの末尾に追加
+    ///
+    /// - $nと${n}はn番目のグループ、$nameと${name}は名前付きグループに置き換える
+    /// - $の後の名前は、英数字とアンダースコアが続く限り読み進める。
+    ///   そのため、$1aはグループ1の後にaを続けるのではなく、1aという名前のグループとなり、
+    ///   この場合は${1}aと書く
+    /// - 存在しないグループや、マッチに関与しなかったグループは空文字列となる
+    /// - $$は$となる
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let re = Regex::new("(?P<y>[0-9]+)-([0-9]+)").unwrap();
+    /// let caps = re.captures("2024-01").unwrap().unwrap();
+    /// let mut dst = String::new();
+    /// caps.expand("$2/${y} ${2}x $$1", &mut dst);
+    /// assert_eq!(dst, "01/2024 01x $1");
+    /// ```
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+        while let Some(i) = rest.find('$') {
+            dst.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            if let Some(r) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = r;
+                continue;
+            }
+
+            let (name, r) = if let Some(r) = rest.strip_prefix('{') {
+                match r.find('}') {
+                    Some(j) if j > 0 => (&r[..j], &r[j + 1..]),
+                    _ => {
+                        // ${や${}は参照とみなさない
+                        dst.push('$');
+                        continue;
+                    }
+                }
+            } else {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    dst.push('$');
+                    continue;
+                }
+                (&rest[..len], &rest[len..])
+            };
+            rest = r;
+
+            let m = match name.parse::<usize>() {
+                Ok(i) => self.get(i),
+                Err(_) => self.name(name),
+            };
+            if let Some(m) = m {
+                dst.push_str(m.as_str());
+            }
+        }
+        dst.push_str(rest);
+    }
+}
+
+impl Regex {
+    /// textの中で最も左のマッチを、repで置き換えた文字列を返す
+    ///
+    /// マッチしなかった場合は、textをそのまま返す
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let re = Regex::new("(?P<last>[^ ]+) (?P<first>[^ ]+)").unwrap();
+    /// assert_eq!(re.replace("Yamada Taro", "$first $last").unwrap(), "Taro Yamada");
+    /// ```
+    pub fn replace<'t, R: Replacer>(
+        &self,
+        text: &'t str,
+        rep: R,
+    ) -> Result<Cow<'t, str>, DynError> {
+        self.replacen(text, 1, rep)
+    }
+
+    /// textの中の重なり合わないすべてのマッチを、repで置き換えた文字列を返す
+    ///
+    /// マッチする位置はfind_iterと同じ
+    ///
+    /// ```
+    /// use regex_engine::engine::{Captures, Regex};
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let doubled = re.replace_all("1と22", |caps: &Captures| {
+    ///     let n: usize = caps.get(0).unwrap().as_str().parse().unwrap();
+    ///     (n * 2).to_string()
+    /// });
+    /// assert_eq!(doubled.unwrap(), "2と44");
+    /// ```
+    pub fn replace_all<'t, R: Replacer>(
+        &self,
+        text: &'t str,
+        rep: R,
+    ) -> Result<Cow<'t, str>, DynError> {
+        self.replacen(text, 0, rep)
+    }
+
+    /// textの中の重なり合わないマッチを、左から最大limit個までrepで置き換えた文字列を返す
+    ///
+    /// limitが0の場合は、すべてのマッチを置き換える
+    pub fn replacen<'t, R: Replacer>(
+        &self,
+        text: &'t str,
+        limit: usize,
+        mut rep: R,
+    ) -> Result<Cow<'t, str>, DynError> {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut dst = String::new();
+        let mut last = 0;
+        let mut replaced = false;
+
+        if let Some(s) = rep.no_expansion() {
+            // キャプチャグループの範囲は不要なため、マッチ全体のみを求める
+            for m in self.find_iter(text).take(limit) {
+                let m = m?;
+                dst.push_str(&text[last..m.start()]);
+                dst.push_str(&s);
+                last = m.end();
+                replaced = true;
+            }
+        } else {
+            for caps in self.captures_iter(text).take(limit) {
+                let caps = caps?;
+                let m = caps.get(0).unwrap();
+                dst.push_str(&text[last..m.start()]);
+                rep.replace_append(&caps, &mut dst);
+                last = m.end();
+                replaced = true;
+            }
+        }
+
+        if !replaced {
+            return Ok(Cow::Borrowed(text));
+        }
+        dst.push_str(&text[last..]);
+        Ok(Cow::Owned(dst))
+    }
+
+    /// textを、正規表現にマッチした部分で区切った部分文字列を返すイテレータを生成
+    ///
+    /// 区切る位置はfind_iterと同じ
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let re = Regex::new("[,、] *").unwrap();
+    /// let fields: Result<Vec<&str>, _> = re.split("a, b、c,,d").collect();
+    /// assert_eq!(fields.unwrap(), ["a", "b", "c", "", "d"]);
+    /// ```
+    pub fn split<'r, 't>(&'r self, text: &'t str) -> Split<'r, 't> {
+        Split {
+            finder: self.find_iter(text),
+            text,
+            last: 0,
+            done: false,
+        }
+    }
+}
+
+/// Regex::splitが返すイテレータ
+pub struct Split<'r, 't> {
+    finder: Matches<'r, 't>,
+    text: &'t str,
+    last: usize, // 次の部分文字列の開始位置（バイト単位）
+    done: bool,
+}
+
+impl<'r, 't> Iterator for Split<'r, 't> {
+    type Item = Result<&'t str, DynError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.finder.next() {
+            Some(Ok(m)) => {
+                let s = &self.text[self.last..m.start()];
+                self.last = m.end();
+                Some(Ok(s))
+            }
+            Some(Err(e)) => {
+                self.done = true;
+                Some(Err(e))
+            }
+            None => {
+                // 最後のマッチ以降の部分文字列
+                self.done = true;
+                Some(Ok(&self.text[self.last..]))
+            }
+        }
+    }
+}
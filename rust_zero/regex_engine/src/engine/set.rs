@@ -0,0 +1,161 @@
+//! 複数の正規表現を1度の走査で評価する正規表現の集合
+use super::{codegen, evaluator, optimizer, parser, Instruction};
+use crate::helper::DynError;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+#[derive(Debug)]
+pub enum SetError {
+    InvalidPattern(usize, DynError), // (正規表現の番号, エラー)
+}
+
+impl Display for SetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetError::InvalidPattern(id, err) => {
+                write!(f, "SetError: invalid pattern: index = {id}, {err}")
+            }
+        }
+    }
+}
+
+impl Error for SetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SetError::InvalidPattern(_, err) => Some(err.as_ref()),
+        }
+    }
+}
+
+/// コンパイル済みの正規表現の集合
+///
+/// すべての正規表現を1つの命令列にまとめ、i番目の正規表現のマッチをMatch(i)命令で表す。
+/// 幅優先探索で評価するため、正規表現の数によらず文字列の走査は1度で済む。
+/// 後方参照は幅優先探索で評価できないため、含まれる場合はErrを返す
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::engine::RegexSet;
+/// let set = RegexSet::new(["error", "warn(ing)?", "^[0-9]+$"]).unwrap();
+/// let matches = set.matches("warning: disk error").unwrap();
+/// assert_eq!(matches.iter().collect::<Vec<_>>(), [0, 1]);
+/// assert!(!matches.matched(2));
+/// ```
+#[derive(Debug)]
+pub struct RegexSet {
+    exprs: Vec<String>,
+    code: Vec<Instruction>,
+}
+
+impl RegexSet {
+    /// 正規表現の列をコンパイルする
+    ///
+    /// 入力された正規表現にエラーがある場合は、その番号を含むSetError::InvalidPatternを返す
+    pub fn new<I, S>(exprs: I) -> Result<RegexSet, DynError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let exprs: Vec<String> = exprs.into_iter().map(|e| e.as_ref().to_string()).collect();
+
+        let mut asts = Vec::new();
+        for (id, expr) in exprs.iter().enumerate() {
+            let invalid = |err| SetError::InvalidPattern(id, err);
+            let ast = parser::parse(expr).map_err(invalid)?;
+            if evaluator::backref_slots(&codegen::get_code(&ast)?) > 0 {
+                let err = Box::new(evaluator::EvalError::BackRefNotSupported);
+                return Err(Box::new(invalid(err)));
+            }
+            asts.push(ast);
+        }
+
+        // 正規表現がない場合は、何にもマッチしない
+        let code = if asts.is_empty() {
+            Vec::new()
+        } else {
+            optimizer::optimize(codegen::get_set_code(&asts)?)?
+        };
+
+        Ok(RegexSet { exprs, code })
+    }
+
+    /// 正規表現の数を返す
+    pub fn len(&self) -> usize {
+        self.exprs.len()
+    }
+
+    /// 正規表現が1つもない場合にtrueを返す
+    pub fn is_empty(&self) -> bool {
+        self.exprs.is_empty()
+    }
+
+    /// コンパイル元の正規表現を、番号順に返す
+    pub fn patterns(&self) -> &[String] {
+        &self.exprs
+    }
+
+    /// textの中に、いずれかの正規表現にマッチする部分文字列があるかを判定
+    ///
+    /// 最初のマッチが見つかった時点で評価を打ち切る
+    pub fn is_match(&self, text: &str) -> Result<bool, DynError> {
+        if self.code.is_empty() {
+            return Ok(false);
+        }
+        let config = evaluator::EvalConfig::default();
+        let slots = evaluator::eval_slots(&self.code, text.as_bytes(), 0, 0, false, &config)?;
+        Ok(slots.is_some())
+    }
+
+    /// textの中に部分文字列がマッチする正規表現を、すべて求める
+    pub fn matches(&self, text: &str) -> Result<SetMatches, DynError> {
+        self.matches_bytes(text.as_bytes())
+    }
+
+    /// バイト列bytesの中に部分がマッチする正規表現を、すべて求める
+    ///
+    /// bytesはUTF-8としてデコードしながら評価し、不正なバイトは1バイトずつU+FFFDとみなす
+    pub fn matches_bytes(&self, bytes: &[u8]) -> Result<SetMatches, DynError> {
+        let matched = evaluator::eval_set(&self.code, bytes, self.exprs.len())?;
+        Ok(SetMatches { matched })
+    }
+}
+
+/// RegexSetで、各正規表現がマッチしたかを表す
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetMatches {
+    matched: Vec<bool>,
+}
+
+impl SetMatches {
+    /// いずれかの正規表現がマッチした場合にtrueを返す
+    pub fn matched_any(&self) -> bool {
+        self.matched.contains(&true)
+    }
+
+    /// i番目の正規表現がマッチした場合にtrueを返す
+    pub fn matched(&self, i: usize) -> bool {
+        self.matched.get(i).copied().unwrap_or(false)
+    }
+
+    /// 正規表現の数を返す
+    pub fn len(&self) -> usize {
+        self.matched.len()
+    }
+
+    /// 正規表現が1つもない場合にtrueを返す
+    pub fn is_empty(&self) -> bool {
+        self.matched.is_empty()
+    }
+
+    /// マッチした正規表現の番号を、昇順に返すイテレータを生成
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.matched
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| **m)
+            .map(|(i, _)| i)
+    }
+}
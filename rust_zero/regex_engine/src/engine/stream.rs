@@ -0,0 +1,290 @@
+//! io::Readから読み込みながら評価するストリーミングマッチ
+//!
+//! 入力全体をメモリに読み込まず、チャンクごとに読み込んだバイト列を幅優先探索の評価器に与える。
+//! 評価器は1文字ずつスレッドを進めるだけなので、マッチがチャンクの境界をまたいでも問題なく求まる。
+//! 保持するバッファは、評価中の位置の前後数バイトと、次の探索を開始する位置以降のみとなる
+use super::{
+    evaluator::{self, EvalError},
+    regex::Regex,
+    text::Text,
+    Instruction,
+};
+use crate::helper::DynError;
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{self, Display},
+    io::{self, Read},
+    ops::Range,
+};
+
+/// 1度に読み込むバイト数
+const CHUNK_SIZE: usize = 8192;
+
+/// 位置spの評価に必要な、sp以降のバイト数。
+/// spの文字（最大4バイト）と、その次の位置でのアサーションの判定に用いる文字（最大4バイト）
+const LOOKAHEAD: usize = 8;
+
+/// 位置spの評価に必要な、spより前のバイト数。^や\bの判定で直前の文字をデコードするために用いる
+const HISTORY: usize = 4;
+
+#[derive(Debug)]
+pub enum StreamError {
+    LookNotSupported,
+}
+
+impl Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::LookNotSupported => {
+                write!(f, "StreamError: look-around is not supported in streaming")
+            }
+        }
+    }
+}
+
+impl Error for StreamError {}
+
+impl Regex {
+    /// readerから読み込みながら、重なり合わないマッチを左から順に返すイテレータを生成
+    ///
+    /// マッチする位置はfind_iter_bytesと同じで、位置は入力の先頭からのバイト単位となる。
+    /// RegexBuilderの設定によらず幅優先探索で評価するため、後方参照を含む場合はErrを返す。
+    /// 先読みは入力の先を無制限に参照しうるため、後読みとともにErrを返す
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::engine::Regex;
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let input: &[u8] = b"abc 123 def 45";
+    /// let ranges = re
+    ///     .stream_matches(input)
+    ///     .unwrap()
+    ///     .map(|m| m.unwrap().range())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(ranges, [4..7, 12..14]);
+    /// ```
+    pub fn stream_matches<R: Read>(&self, reader: R) -> Result<StreamMatches<'_, R>, DynError> {
+        if evaluator::backref_slots(&self.code) > 0 {
+            return Err(Box::new(EvalError::BackRefNotSupported));
+        }
+        if self
+            .code
+            .iter()
+            .any(|i| matches!(i, Instruction::Look(_, _)))
+        {
+            return Err(Box::new(StreamError::LookNotSupported));
+        }
+
+        Ok(StreamMatches {
+            regex: self,
+            reader,
+            buf: Vec::new(),
+            base: 0,
+            eof: false,
+            done: false,
+            last_end: 0,
+            last_match: None,
+        })
+    }
+}
+
+/// ストリーム中のマッチした部分
+///
+/// 位置は入力の先頭からのバイト単位となる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamMatch {
+    start: usize,
+    end: usize,
+}
+
+impl StreamMatch {
+    /// マッチの開始位置
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// マッチの終了位置
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// マッチした範囲
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// 読み込み済みのバイト列の一部を、入力の先頭からの位置で参照する
+///
+/// 入力の末尾に達するまでは文字列の長さが未確定のため、len()はusize::MAXとする。
+/// 評価する位置の前後のバイトがbufにあることは、StreamMatchesが保証する
+struct Window<'a> {
+    buf: &'a [u8],
+    base: usize, // buf[0]の入力の先頭からの位置
+    eof: bool,
+}
+
+impl Text for Window<'_> {
+    fn len(&self) -> usize {
+        if self.eof {
+            self.base + self.buf.len()
+        } else {
+            usize::MAX
+        }
+    }
+
+    fn char_at(&self, sp: usize) -> Option<(char, usize)> {
+        self.buf.char_at(sp.checked_sub(self.base)?)
+    }
+
+    fn char_before(&self, sp: usize) -> Option<char> {
+        self.buf.char_before(sp.checked_sub(self.base)?)
+    }
+
+    fn prev_pos(&self, sp: usize) -> usize {
+        self.base + self.buf.prev_pos(sp.saturating_sub(self.base))
+    }
+}
+
+/// Regex::stream_matchesが返すイテレータ
+///
+/// 読み込みに失敗した場合はErrを返し、以降は探索を打ち切る
+pub struct StreamMatches<'r, R> {
+    regex: &'r Regex,
+    reader: R,
+    buf: Vec<u8>,              // 読み込み済みで、まだ必要なバイト列
+    base: usize,               // buf[0]の入力の先頭からの位置
+    eof: bool,                 // 入力の末尾まで読み込んだか
+    done: bool,                // 探索を終えたか
+    last_end: usize,           // 次に探索を開始する位置
+    last_match: Option<usize>, // 直前のマッチの終了位置
+}
+
+impl<R: Read> StreamMatches<'_, R> {
+    fn window(&self) -> Window<'_> {
+        Window {
+            buf: &self.buf,
+            base: self.base,
+            eof: self.eof,
+        }
+    }
+
+    /// 位置spの評価に必要なバイト列を、入力の末尾に達しない限り読み込む
+    fn fill(&mut self, sp: usize) -> Result<(), DynError> {
+        while !self.eof && self.base + self.buf.len() < sp + LOOKAHEAD {
+            let len = self.buf.len();
+            self.buf.resize(len + CHUNK_SIZE, 0);
+            let n = loop {
+                match self.reader.read(&mut self.buf[len..]) {
+                    Ok(n) => break n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        self.buf.truncate(len);
+                        return Err(Box::new(e));
+                    }
+                }
+            };
+            self.buf.truncate(len + n);
+            self.eof = n == 0;
+        }
+        Ok(())
+    }
+
+    /// 位置keepより前の不要なバイト列を破棄する
+    ///
+    /// 直前の文字のデコード用にHISTORYバイトは残す。
+    /// 破棄のたびにバッファを詰め直さないよう、CHUNK_SIZE以上溜まった場合のみ破棄する
+    fn discard(&mut self, keep: usize) {
+        let n = keep.saturating_sub(HISTORY).saturating_sub(self.base);
+        if n >= CHUNK_SIZE {
+            self.buf.drain(..n);
+            self.base += n;
+        }
+    }
+
+    /// 位置spから探索を開始し、最も左から始まるマッチの範囲を返す
+    fn find_at(&mut self, sp: usize) -> Result<Option<(usize, usize)>, DynError> {
+        self.fill(sp)?;
+        if self.eof && sp > self.base + self.buf.len() {
+            return Ok(None);
+        }
+
+        let regex = self.regex;
+        let inst = &regex.code;
+        let mut clist = VecDeque::new(); // 現在の文字位置で実行するスレッド
+        let mut nlist = VecDeque::new(); // 次の文字位置で実行するスレッド
+        let mut visited = vec![false; inst.len()];
+        let mut matched = None;
+
+        let thread = (0, vec![None; 2]);
+        evaluator::add_thread(inst, &self.window(), sp, &mut clist, &mut visited, thread)?;
+
+        let mut sp = sp;
+        while !clist.is_empty() {
+            self.fill(sp)?;
+            let window = self.window();
+            let slots = evaluator::step_width(
+                inst,
+                &window,
+                sp,
+                None,
+                &mut clist,
+                &mut nlist,
+                &mut visited,
+            )?;
+            if let Some(slots) = slots {
+                match slots[..] {
+                    [Some(start), Some(end)] => matched = Some((start, end)),
+                    _ => unreachable!(), // マッチした場合は、Save(0)とSave(1)を必ず実行する
+                }
+            }
+            std::mem::swap(&mut clist, &mut nlist);
+            sp = window.next_pos(sp);
+
+            // 次の探索はマッチの終了位置から開始するため、それ以降は残す
+            let keep = matched.map_or(sp, |(_, end)| end.min(sp));
+            self.discard(keep);
+        }
+
+        Ok(matched)
+    }
+}
+
+impl<R: Read> Iterator for StreamMatches<'_, R> {
+    type Item = Result<StreamMatch, DynError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.done {
+                return None;
+            }
+
+            let (start, end) = match self.find_at(self.last_end) {
+                Ok(Some(m)) => m,
+                Ok(None) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => {
+                    // エラー後は探索を打ち切る
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+
+            if start == end {
+                // 長さ0のマッチの場合は、無限ループしないよう次の文字から探索する
+                self.last_end = self.window().next_pos(end);
+                if self.last_match == Some(end) {
+                    continue;
+                }
+            } else {
+                self.last_end = end;
+            }
+            self.last_match = Some(end);
+            return Some(Ok(StreamMatch { start, end }));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! 評価器に与える入力文字列
+//!
+//! 評価器はUTF-8のバイト列&[u8]を、評価しながらデコードする。
+//! そのため文字の配列を生成する必要がなく、位置spはバイト単位となる
+//!
+//! UTF-8として不正なバイトは、1バイトずつU+FFFD（REPLACEMENT CHARACTER）とみなす。
+//! そのため、.や[^a]は不正なバイトにもマッチする
+
+/// 評価器に与える入力文字列
+pub trait Text {
+    /// 文字列の長さ。位置spは0からlen()までとなる
+    fn len(&self) -> usize;
+
+    /// 位置spの文字と、その文字の長さを返す。spが末尾の場合はNoneを返す
+    fn char_at(&self, sp: usize) -> Option<(char, usize)>;
+
+    /// 位置spの直前の文字を返す。spが先頭の場合はNoneを返す
+    fn char_before(&self, sp: usize) -> Option<char>;
+
+    /// 位置spの直前の文字の位置を返す。spが先頭の場合は0を返す
+    fn prev_pos(&self, sp: usize) -> usize;
+
+    /// 位置spの文字の次の文字の位置を返す。spが末尾の場合はsp + 1を返す
+    fn next_pos(&self, sp: usize) -> usize {
+        match self.char_at(sp) {
+            Some((_, len)) => sp + len,
+            None => sp + 1,
+        }
+    }
+}
+
+impl Text for [u8] {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    fn char_at(&self, sp: usize) -> Option<(char, usize)> {
+        let b = *self.get(sp)?;
+        if b < 0x80 {
+            return Some((b as char, 1));
+        }
+
+        // 先頭バイトから長さを求め、その範囲がUTF-8として正しいかを確認する。
+        // 冗長な表現やサロゲートはfrom_utf8が不正とする
+        let len = match b {
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => return Some((char::REPLACEMENT_CHARACTER, 1)),
+        };
+        match self.get(sp..sp + len).map(std::str::from_utf8) {
+            Some(Ok(s)) => s.chars().next().map(|c| (c, len)),
+            _ => Some((char::REPLACEMENT_CHARACTER, 1)),
+        }
+    }
+
+    fn char_before(&self, sp: usize) -> Option<char> {
+        if sp == 0 || sp > self.len() {
+            return None;
+        }
+
+        // 1から4バイト前までを調べ、spでちょうど終わる正しい文字があればそれを返す
+        for len in 1..=4.min(sp) {
+            if let Some((c, l)) = self.char_at(sp - len) {
+                if l == len && c != char::REPLACEMENT_CHARACTER {
+                    return Some(c);
+                }
+            }
+        }
+        Some(char::REPLACEMENT_CHARACTER)
+    }
+
+    fn prev_pos(&self, sp: usize) -> usize {
+        // 2から4バイト前までを調べ、spでちょうど終わる複数バイトの文字があればその位置を返す。
+        // それ以外の場合は、ASCII文字か不正なバイトとして1バイト前を返す
+        for len in 2..=4.min(sp) {
+            if let Some((_, l)) = self.char_at(sp - len) {
+                if l == len {
+                    return sp - len;
+                }
+            }
+        }
+        sp.saturating_sub(1)
+    }
+}
+
+/// バイト列をデコードしながら、先頭から1文字ずつ返すイテレータを生成
+pub fn chars(bytes: &[u8]) -> impl Iterator<Item = char> + '_ {
+    let mut sp = 0;
+    std::iter::from_fn(move || {
+        let (c, len) = bytes.char_at(sp)?;
+        sp += len;
+        Some(c)
+    })
+}
+
+/// バイト列haystackの中で、needleが最初に現れる位置を返す
+///
+/// needleの先頭バイトが現れる位置を探し、そこから一致するかを確認する
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    let (first, rest) = match needle.split_first() {
+        Some(split) => split,
+        None => return Some(0),
+    };
+
+    let mut start = 0;
+    while let Some(i) = haystack[start..].iter().position(|b| b == first) {
+        let pos = start + i;
+        if haystack[pos + 1..].starts_with(rest) {
+            return Some(pos);
+        }
+        start = pos + 1;
+    }
+    None
+}
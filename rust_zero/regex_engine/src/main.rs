@@ -1,62 +1,138 @@
-mod engine;
-mod helper;
+mod cli;
 
-use helper::DynError;
+use cli::{Color, DebugFormat, Options, Searcher, USAGE};
+use regex_engine::{
+    engine::{self, ParseError},
+    helper::DynError,
+};
 use std::{
     env,
-    fs::File,
-    io::{BufRead, BufReader},
+    io::{self, BufWriter, IsTerminal, Write},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-/// ファイルをオープンし、行ごとにマッチングを行う
-///
-/// マッチングはそれぞれの行頭から1文字ずつずらして行い、
-/// いずれかにマッチした場合に、その行がマッチしたものとする
+/// 引数に従ってファイルや標準入力を探索する
 ///
-/// たとえば、abcdという文字列があった場合、以下の順にマッチが行われ、
-/// このいずれかにマッチした場合、与えられた正規表現にマッチする行と判定する
+/// 各行の任意の位置から始まる部分文字列が正規表現にマッチした場合に、
+/// その行がマッチしたものとする。
+/// 行頭や行末に限定したい場合は、^や$を利用する
 ///
-/// - abcd
-/// - bcd
-/// - cd
-/// - d
-fn match_file(expr: &str, file: &str) -> Result<(), DynError> {
-    let f = File::open(file)?; // read-onlyモードでファイルを開く
-    let reader = BufReader::new(f);
+/// grepと同様に、マッチした行があった場合は0を、なかった場合は1を、
+/// 読み込みに失敗したファイルがあった場合は2を返す。
+/// オプションに誤りがある場合は、エラーとともに使い方を表示して2を返す
+fn run() -> Result<i32, DynError> {
+    let opts = match Options::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("regex_engine: {e}");
+            eprintln!("{USAGE}");
+            return Ok(2);
+        }
+    };
+    if opts.help {
+        println!("{USAGE}");
+        return Ok(0);
+    }
 
-    engine::print(expr)?;
-    println!();
+    // 正規表現のコンパイルは最初に1度だけ行う。
+    // パースエラーの場合は、エラーの位置を示す
+    let regex = opts
+        .build_regex()
+        .map_err(|e| match e.downcast_ref::<ParseError>() {
+            Some(e) => e.render(&opts.pattern).into(),
+            None => e,
+        })?;
 
-    for line in reader.lines() {
-        let line = line?;
-        for (i, _) in line.char_indices() {
-            // UTF-8の文字単位でのインデックスを取得する
-            if engine::do_matching(expr, &line[i..], true)? {
-                println!("{line}");
-                break;
-            }
+    // 出力はすべて同じバッファを通し、パイプが閉じられた場合はErrとしてmainに返す
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    match opts.debug {
+        Some(DebugFormat::Text) => {
+            engine::print_regex(&regex, true, &mut out)?;
+            writeln!(out)?;
+        }
+        Some(DebugFormat::Dot) => {
+            write!(out, "{}", engine::export_dot(&regex))?;
+            out.flush()?;
+            return Ok(0);
         }
+        Some(DebugFormat::Json) => {
+            writeln!(out, "{}", engine::export_json(&regex))?;
+            out.flush()?;
+            return Ok(0);
+        }
+        None => (),
     }
 
-    Ok(())
-}
+    if let Some(n) = opts.generate {
+        let seed = opts.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+        });
+        cli::generate(&regex, n, opts.near_miss, seed, &mut out)?;
+        out.flush()?;
+        return Ok(0);
+    }
+
+    let color = match opts.color {
+        Color::Always => true,
+        Color::Never => false,
+        Color::Auto => io::stdout().is_terminal(),
+    };
 
-fn main() -> Result<(), DynError> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
-        eprintln!("usage: {} regex file", args[0]);
-        return Err("invalid arguments".into());
+    let files = if !opts.files.is_empty() {
+        opts.files.clone()
+    } else if opts.recursive {
+        vec![".".to_string()]
     } else {
-        match_file(&args[1], &args[2])?;
+        vec!["-".to_string()]
+    };
+
+    let mut searcher = Searcher::new(&regex, &opts, color, &mut out);
+    for file in files.iter() {
+        searcher.search_path(file)?;
     }
 
-    Ok(())
+    let code = if searcher.has_error {
+        2
+    } else if searcher.is_matched {
+        0
+    } else {
+        1
+    };
+    searcher.into_inner().flush()?;
+    Ok(code)
+}
+
+fn main() {
+    let code = match run() {
+        Ok(code) => code,
+        Err(e) => {
+            // パイプの読み込み側が閉じられた場合は、エラーを表示せずに終了
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+            {
+                0
+            } else {
+                eprintln!("regex_engine: {e}");
+                2
+            }
+        }
+    };
+    process::exit(code);
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        engine::do_matching,
+    use super::cli::{self, Color, DebugFormat, Options, Searcher};
+    use regex_engine::{
+        engine::{
+            check_evaluators, check_evaluators_random, do_matching, do_searching, export_dot,
+            export_json, Captures, Comparison, DifferentialError, EquivError, GenerateError,
+            NoExpand, ParseError, Regex, RegexBuilder, RegexSet, SetError, Span,
+        },
         helper::{safe_add, SafeAdd},
     };
 
@@ -93,5 +169,1641 @@ mod tests {
         assert!(!do_matching("abc|def", "efa", true).unwrap());
         assert!(!do_matching("(ab|cd)+", "", true).unwrap());
         assert!(!do_matching("abc?", "acb", true).unwrap());
+
+        // 幅優先探索
+        assert!(do_matching("+b", "bbb", false).is_err());
+        assert!(do_matching("abc|def", "def", false).unwrap());
+        assert!(do_matching("(abc)*", "abcabc", false).unwrap());
+        assert!(do_matching("(ab|cd)+", "abcdcd", false).unwrap());
+        assert!(do_matching("abc?", "ab", false).unwrap());
+        assert!(!do_matching("abc|def", "efa", false).unwrap());
+        assert!(!do_matching("(ab|cd)+", "", false).unwrap());
+        assert!(!do_matching("abc?", "acb", false).unwrap());
+    }
+
+    #[test]
+    fn test_depth_width() {
+        // 深さ優先探索と幅優先探索の結果が一致することを確認
+        let exprs = [
+            "abc|def",
+            "(abc)*d",
+            "(ab|cd)+",
+            "abc?",
+            "a?a?a?aaa",
+            "(a|b)*abb",
+            "a(b|c)+d?",
+        ];
+        let lines = [
+            "", "a", "ab", "abc", "def", "abcabcd", "abcdcd", "aaa", "babb", "abcbd", "acd",
+        ];
+        for expr in exprs {
+            for line in lines {
+                assert_eq!(
+                    do_matching(expr, line, true).unwrap(),
+                    do_matching(expr, line, false).unwrap(),
+                    "expr = {expr}, line = {line}"
+                );
+            }
+        }
+
+        // 深さ優先探索ではスタックが溢れるパターンも、幅優先探索なら線形時間で評価できる
+        let line = "a".repeat(10000);
+        assert!(!do_matching("(a*)*b", &line, false).unwrap());
+        assert!(do_matching("(a*)*b", &format!("{line}b"), false).unwrap());
+    }
+
+    #[test]
+    fn test_class() {
+        // パースエラー
+        assert!(do_matching("[abc", "a", true).is_err());
+        assert!(do_matching("[z-a]", "a", true).is_err());
+        assert!(do_matching("[a\\q]", "a", true).is_err());
+
+        for is_depth in [true, false] {
+            assert!(do_matching("[a-z0-9_]+", "abc_123", is_depth).unwrap());
+            assert!(!do_matching("[a-z0-9_]+", "ABC", is_depth).unwrap());
+            assert!(do_matching("\"[^\"]*\"", "\"quoted\"", is_depth).unwrap());
+            assert!(!do_matching("\"[^\"]*\"", "\"unclosed", is_depth).unwrap());
+            assert!(!do_matching("[^abc]", "b", is_depth).unwrap());
+            assert!(do_matching("[ぁ-ん]+", "ひらがな", is_depth).unwrap());
+
+            // 先頭の]と、先頭か末尾の-は通常の文字
+            assert!(do_matching("[]a]", "]", is_depth).unwrap());
+            assert!(do_matching("[a-]", "-", is_depth).unwrap());
+            assert!(do_matching("[-a]", "-", is_depth).unwrap());
+            assert!(do_matching("[\\]\\-]", "-", is_depth).unwrap());
+            assert!(do_matching("\\[a\\]", "[a]", is_depth).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_searching() {
+        for is_depth in [true, false] {
+            // do_matchingは先頭からのマッチのみ、do_searchingは任意の位置からのマッチを判定
+            assert!(!do_matching("bc", "abcd", is_depth).unwrap());
+            assert!(do_searching("bc", "abcd", is_depth).unwrap());
+            assert!(!do_searching("bd", "abcd", is_depth).unwrap());
+            assert!(do_searching("", "", is_depth).is_err());
+            assert!(do_searching("c", "ab\nc", is_depth).unwrap());
+            assert!(do_searching("カ+", "ひらがなとカタカナ", is_depth).unwrap());
+
+            // アンカー
+            assert!(do_searching("^ab", "abcd", is_depth).unwrap());
+            assert!(!do_searching("^bc", "abcd", is_depth).unwrap());
+            assert!(do_searching("cd$", "abcd", is_depth).unwrap());
+            assert!(!do_searching("bc$", "abcd", is_depth).unwrap());
+            assert!(do_searching("^abcd$", "abcd", is_depth).unwrap());
+            assert!(!do_searching("^abc$", "abcd", is_depth).unwrap());
+            assert!(do_searching("^$", "", is_depth).unwrap());
+            assert!(do_searching("a|^b", "cba", is_depth).unwrap());
+            assert!(!do_searching("c^|b$", "cba", is_depth).unwrap());
+            assert!(do_searching("\\^\\$", "a^$b", is_depth).unwrap());
+            assert!(do_matching("ab$", "ab", is_depth).unwrap());
+            assert!(!do_matching("ab$", "abc", is_depth).unwrap());
+        }
+
+        // 長い行でも探索の再帰が深くならない
+        let line = format!("{}b", "a".repeat(100000));
+        assert!(do_searching("ab$", &line, true).unwrap());
+    }
+
+    #[test]
+    fn test_regex() {
+        assert!(Regex::new("(a").is_err());
+
+        for is_depth in [true, false] {
+            let re = RegexBuilder::new("[0-9]+")
+                .depth_first(is_depth)
+                .build()
+                .unwrap();
+            assert_eq!(re.as_str(), "[0-9]+");
+
+            // コンパイル済みの正規表現を複数の行に使い回す
+            assert!(re.is_match("abc 123").unwrap());
+            assert!(!re.is_match("abc def").unwrap());
+            assert!(re.is_match("0").unwrap());
+
+            let m = re.find("abc 123 def 45").unwrap().unwrap();
+            assert_eq!((m.start(), m.end(), m.as_str()), (4, 7, "123"));
+            assert!(re.find("abc").unwrap().is_none());
+
+            let all = re
+                .find_iter("abc 123 def 45 6")
+                .map(|m| m.unwrap().as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(all, vec!["123", "45", "6"]);
+
+            // 位置はバイト単位
+            let m = re.find("あいう12").unwrap().unwrap();
+            assert_eq!(m.range(), 9..11);
+            assert_eq!(m.as_str(), "12");
+
+            let caps = re.captures("x99y").unwrap().unwrap();
+            assert_eq!(caps.len(), 1);
+            assert_eq!(caps.get(0).unwrap().as_str(), "99");
+            assert!(caps.get(1).is_none());
+
+            // 長さ0のマッチ
+            let re = RegexBuilder::new("a*")
+                .depth_first(is_depth)
+                .build()
+                .unwrap();
+            let all = re
+                .find_iter("baaあa")
+                .map(|m| m.unwrap().range())
+                .collect::<Vec<_>>();
+            assert_eq!(all, vec![0..0, 1..3, 6..7]);
+
+            let re = RegexBuilder::new("^$")
+                .depth_first(is_depth)
+                .build()
+                .unwrap();
+            assert_eq!(re.find_iter("").count(), 1);
+            assert_eq!(re.find_iter("a").count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_captures() {
+        // パースエラー
+        assert!(Regex::new("(?P<1a>x)").is_err());
+        assert!(Regex::new("(?P<a>x)(?<a>y)").is_err());
+        assert!(Regex::new("(?x)").is_err());
+        assert!(Regex::new("(?P<a").is_err());
+
+        let re = Regex::new("(a)(?:b)(?P<name>c)").unwrap();
+        assert_eq!(re.captures_len(), 3);
+        assert_eq!(
+            re.capture_names().collect::<Vec<_>>(),
+            vec![None, None, Some("name")]
+        );
+
+        // 深さ優先探索と幅優先探索で同じ範囲が得られる
+        let cases: &[(&str, &str, &[Option<&str>])] = &[
+            ("(a)(b)?c", "xac", &[Some("ac"), Some("a"), None]),
+            (
+                "(a|ab)(c|bcd)(d*)",
+                "abcd",
+                &[Some("abcd"), Some("a"), Some("bcd"), Some("")],
+            ),
+            ("(a*)b", "b", &[Some("b"), Some("")]),
+            ("(a+)(a+)", "aaaa", &[Some("aaaa"), Some("aaa"), Some("a")]),
+            ("((a)|b)+", "ab", &[Some("ab"), Some("b"), Some("a")]),
+            ("()", "x", &[Some(""), Some("")]),
+            ("(?:(\\d+)-)+", "1-22-x", &[Some("1-22-"), Some("22")]),
+            (
+                "(あ+)(い)",
+                "んああい",
+                &[Some("ああい"), Some("ああ"), Some("い")],
+            ),
+        ];
+        for (expr, text, expected) in cases {
+            for is_depth in [true, false] {
+                let re = RegexBuilder::new(expr)
+                    .depth_first(is_depth)
+                    .build()
+                    .unwrap();
+                let caps = re.captures(text).unwrap().unwrap();
+                assert_eq!(caps.len(), expected.len(), "expr = {expr}");
+                for (i, e) in expected.iter().enumerate() {
+                    assert_eq!(
+                        caps.get(i).map(|m| m.as_str()),
+                        *e,
+                        "expr = {expr}, i = {i}"
+                    );
+                }
+            }
+        }
+
+        // バイト単位と文字単位の範囲
+        let re = Regex::new("(?P<kana>[ぁ-ん]+)(?P<num>\\d+)").unwrap();
+        let caps = re.captures("漢字ひらがな123").unwrap().unwrap();
+        let kana = caps.name("kana").unwrap();
+        assert_eq!(kana.as_str(), "ひらがな");
+        assert_eq!(kana.range(), 6..18);
+        assert_eq!(kana.char_range(), 2..6);
+        let num = caps.name("num").unwrap();
+        assert_eq!(num.range(), 18..21);
+        assert_eq!(num.char_range(), 6..9);
+        assert!(caps.name("none").is_none());
+        assert!(re.captures("abc").unwrap().is_none());
+
+        // 長さ0のマッチの後は、マルチバイト文字も1文字ずつ進める
+        let re = Regex::new("x*").unwrap();
+        let ranges = re
+            .find_iter("あxい")
+            .map(|m| m.unwrap().range())
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [0..0, 3..4, 7..7]);
+    }
+
+    #[test]
+    fn test_repeat() {
+        // パースエラー
+        assert!(Regex::new("{2}").is_err());
+        assert!(Regex::new("a{").is_err());
+        assert!(Regex::new("a{x}").is_err());
+        assert!(Regex::new("a{,2}").is_err());
+        assert!(Regex::new("a{2,1}").is_err());
+        assert!(Regex::new("a{1,2").is_err());
+        assert!(Regex::new("a{99999999999999999999}").is_err());
+
+        // 命令列が長くなりすぎる場合はコード生成エラー
+        assert!(Regex::new("a{1000}{1000}{1000}").is_err());
+        assert!(Regex::new("(?:a{0}){0,1000000000}").is_err());
+        assert!(Regex::new("(?:a{0}){1000000000}").is_ok());
+
+        for is_depth in [true, false] {
+            let re = RegexBuilder::new("^\\d{4}-\\d{2}$")
+                .depth_first(is_depth)
+                .build()
+                .unwrap();
+            assert!(re.is_match("2024-01").unwrap());
+            assert!(!re.is_match("2024-1").unwrap());
+            assert!(!re.is_match("20245-01").unwrap());
+
+            let find = |expr: &str, text: &str| {
+                let re = RegexBuilder::new(expr)
+                    .depth_first(is_depth)
+                    .build()
+                    .unwrap();
+                re.find(text).unwrap().map(|m| m.as_str().to_string())
+            };
+            assert_eq!(find("a{2,3}", "aaaa"), Some("aaa".to_string()));
+            assert_eq!(find("a{2,}", "aaaaa"), Some("aaaaa".to_string()));
+            assert_eq!(find("a{2,}", "a"), None);
+            assert_eq!(find("a{0}b", "ab"), Some("b".to_string()));
+            assert_eq!(find("(ab){2}", "abababab"), Some("abab".to_string()));
+            assert_eq!(
+                find("[あ-ん]{2,3}", "漢ひらがな"),
+                Some("ひらが".to_string())
+            );
+            assert_eq!(find("x\\{2\\}", "x{2}"), Some("x{2}".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_lazy() {
+        // 限量子のない?は、これまで通りのQuestion
+        assert!(Regex::new("??").is_err());
+
+        let cases: &[(&str, &str, &[&str])] = &[
+            // 貪欲と非貪欲
+            ("<.+>", "<a><b>", &["<a><b>"]),
+            ("<.+?>", "<a><b>", &["<a>", "<b>"]),
+            ("<.*?>", "<><b>", &["<>", "<b>"]),
+            ("a??b", "ab", &["ab"]),
+            ("a??", "aa", &["", "", ""]),
+            ("a{2,4}?", "aaaaa", &["aa", "aa"]),
+            ("a{2,}?", "aaaaa", &["aa", "aa"]),
+            ("\"(.*?)\"", "\"a\" \"b\"", &["\"a\"", "\"b\""]),
+            // leftmost-first: 最も左から始まるマッチを選び、同じ位置では|の左側を優先
+            ("a|ab", "ab", &["a"]),
+            ("ab|a", "ab", &["ab"]),
+            ("b|ab", "ab", &["ab"]),
+            ("(a|ab)(c|bcd)", "abcd", &["abcd"]),
+            ("x*?y", "xxy", &["xxy"]),
+        ];
+        for (expr, text, expected) in cases {
+            for is_depth in [true, false] {
+                let re = RegexBuilder::new(expr)
+                    .depth_first(is_depth)
+                    .build()
+                    .unwrap();
+                let all = re
+                    .find_iter(text)
+                    .map(|m| m.unwrap().as_str())
+                    .collect::<Vec<_>>();
+                assert_eq!(&all, expected, "expr = {expr}, is_depth = {is_depth}");
+            }
+        }
+
+        // 非貪欲な繰り返しでのキャプチャ
+        for is_depth in [true, false] {
+            let re = RegexBuilder::new("(\\w+?)(\\d*)$")
+                .depth_first(is_depth)
+                .build()
+                .unwrap();
+            let caps = re.captures("abc123").unwrap().unwrap();
+            assert_eq!(caps.get(1).unwrap().as_str(), "abc");
+            assert_eq!(caps.get(2).unwrap().as_str(), "123");
+        }
+    }
+
+    #[test]
+    fn test_escape_class() {
+        // パースエラー
+        assert!(do_matching("\\q", "q", true).is_err());
+        assert!(do_matching("\\u{110000}", "a", true).is_err());
+        assert!(do_matching("\\u3042", "あ", true).is_err());
+        assert!(do_matching("\\u{3042", "あ", true).is_err());
+
+        for is_depth in [true, false] {
+            // ドットは改行以外の任意の1文字にマッチ
+            assert!(do_matching("a.c", "abc", is_depth).unwrap());
+            assert!(do_matching("a.c", "aあc", is_depth).unwrap());
+            assert!(!do_matching("a.c", "a\nc", is_depth).unwrap());
+            assert!(do_matching("a\\.c", "a.c", is_depth).unwrap());
+            assert!(!do_matching("a\\.c", "abc", is_depth).unwrap());
+
+            // 定義済み文字クラス
+            assert!(do_matching("\\d+-\\d+", "2024-01", is_depth).unwrap());
+            assert!(!do_matching("\\d", "x", is_depth).unwrap());
+            assert!(do_matching("\\D\\W\\S", "x-y", is_depth).unwrap());
+            assert!(!do_matching("\\D", "5", is_depth).unwrap());
+            assert!(do_matching("\\w+\\s\\w+", "hello_1\tworld", is_depth).unwrap());
+            assert!(!do_matching("\\s", "あ", is_depth).unwrap());
+            assert!(do_matching("[\\d_]+", "1_2", is_depth).unwrap());
+            assert!(do_matching("[\\D]", "あ", is_depth).unwrap());
+            assert!(!do_matching("[^\\w\\s]", "a", is_depth).unwrap());
+            assert!(do_matching("[^\\w\\s]", "!", is_depth).unwrap());
+
+            // \n, \t, \u{...}
+            assert!(do_matching("a\\nb", "a\nb", is_depth).unwrap());
+            assert!(do_matching("a\\tb", "a\tb", is_depth).unwrap());
+            assert!(do_matching("\\u{3042}\\u{1F600}", "あ😀", is_depth).unwrap());
+            assert!(do_matching("[\\u{3041}-\\u{3096}]+", "ひらがな", is_depth).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_lazy_dfa() {
+        let cases = [
+            ("abc|def", vec!["def", "xxabcxx", "abd", ""]),
+            ("^a(b|c)*d$", vec!["abcbd", "ad", "xad", "abcdx", "a"]),
+            ("^$", vec!["", "a"]),
+            ("a$|^b", vec!["ba", "xb", "cab", ""]),
+            ("[0-9]+-[0-9]+", vec!["tel: 03-1234", "03-", "-1234"]),
+            ("カ+ナ", vec!["ひらがなとカタカナ", "カカナ", "カタ"]),
+            ("a.c", vec!["abc", "a\nc", "a\u{3042}c"]),
+            ("(a*)*b", vec!["aaab", "aaaa", "b"]),
+            ("a{2,3}?$", vec!["aaaa", "a"]),
+            ("x*", vec!["", "abc"]),
+        ];
+
+        for (expr, lines) in cases {
+            let vm = Regex::new(expr).unwrap();
+            let dfa = RegexBuilder::new(expr).lazy_dfa(true).build().unwrap();
+            // 状態数の上限を超えた場合は、通常の評価器で評価し直す
+            let small = RegexBuilder::new(expr)
+                .lazy_dfa(true)
+                .dfa_state_limit(1)
+                .build()
+                .unwrap();
+            for line in lines {
+                let expected = vm.is_match(line).unwrap();
+                assert_eq!(dfa.is_match(line).unwrap(), expected, "{expr} {line}");
+                // キャッシュ済みの状態で再評価
+                assert_eq!(dfa.is_match(line).unwrap(), expected, "{expr} {line}");
+                assert_eq!(small.is_match(line).unwrap(), expected, "{expr} {line}");
+            }
+        }
+
+        assert!(RegexBuilder::new("a(b").lazy_dfa(true).build().is_err());
+    }
+
+    #[test]
+    fn test_backtrack_limit() {
+        // 深さ優先探索で指数時間かかる正規表現
+        let expr = "^(a|aa)*b";
+        let line = "a".repeat(40);
+
+        let re = RegexBuilder::new(expr)
+            .depth_first(true)
+            .step_limit(Some(100_000))
+            .build()
+            .unwrap();
+        let err = re.is_match(&line).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvaluatorError: step limit exceeded: limit = 100000"
+        );
+        // 上限内で評価できる場合は通常通りマッチする
+        assert!(re.is_match("aaab").unwrap());
+
+        // メモ化すると線形時間で評価できる
+        let re = RegexBuilder::new(expr)
+            .depth_first(true)
+            .memoize(true)
+            .step_limit(Some(100_000))
+            .build()
+            .unwrap();
+        assert!(!re.is_match(&line).unwrap());
+        assert!(re.is_match(&format!("{line}b")).unwrap());
+        assert_eq!(
+            re.captures("aaab")
+                .unwrap()
+                .unwrap()
+                .get(1)
+                .unwrap()
+                .range(),
+            2..3
+        );
+
+        // 空文字列にマッチする繰り返しの繰り返しも、メモ化すると停止する
+        let re = RegexBuilder::new("(a*)*b")
+            .depth_first(true)
+            .memoize(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("aaab").unwrap());
+        assert!(!re.is_match("aaaa").unwrap());
+
+        // メモ化しない既定の設定でも、空の繰り返しを打ち切るため停止する
+        for (expr, line, expected) in [
+            ("(a*)*b", "b", Some((0..1, None))),
+            ("(a*)*b", "aab", Some((0..3, Some(0..2)))),
+            ("(a*)*b", "aaa", None),
+            ("(x?)*y", "y", Some((0..1, None))),
+            ("(x?)*y", "xxy", Some((0..3, Some(1..2)))),
+            ("(x?)*?y", "xy", Some((0..2, Some(0..1)))),
+            ("(?:^)*a", "ba", Some((1..2, None))),
+        ] {
+            let depth = RegexBuilder::new(expr).depth_first(true).build().unwrap();
+            let width = Regex::new(expr).unwrap();
+            for re in [&depth, &width] {
+                let caps = re.captures(line).unwrap();
+                let ranges = caps.map(|c| (c.get(0).unwrap().range(), c.get(1).map(|m| m.range())));
+                assert_eq!(ranges, expected, "{expr} {line}");
+            }
+        }
+        assert!(do_matching("(a*)*b", "b", true).unwrap());
+        assert!(!do_matching("(a*)*b", "aaa", true).unwrap());
+
+        // バックトラックの深さの上限
+        let re = RegexBuilder::new("a*b")
+            .depth_first(true)
+            .depth_limit(Some(100))
+            .build()
+            .unwrap();
+        assert!(re.is_match(&format!("{}b", "a".repeat(50))).unwrap());
+        let err = re.is_match(&"a".repeat(200)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvaluatorError: depth limit exceeded: limit = 100"
+        );
+
+        // 長い文字列でもスタックオーバーフローしない
+        let re = RegexBuilder::new("^(a)*$")
+            .depth_first(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match(&"a".repeat(100_000)).unwrap());
+
+        // 上限は幅優先探索には影響しない
+        let re = RegexBuilder::new(expr)
+            .step_limit(Some(1))
+            .depth_limit(Some(1))
+            .build()
+            .unwrap();
+        assert!(!re.is_match(&line).unwrap());
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let re = RegexBuilder::new("hello|[x-z]+")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.find("Hi, HeLLo").unwrap().unwrap().as_str(), "HeLLo");
+        assert_eq!(re.find("abc XyZ").unwrap().unwrap().as_str(), "XyZ");
+
+        // 否定クラスは、大文字と小文字の両方を除外する
+        let re = RegexBuilder::new("[^a]+")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.find("aAbB").unwrap().unwrap().as_str(), "bB");
+
+        // 大文字と小文字のない文字はそのまま
+        let re = RegexBuilder::new("ÉTÉ|ひらがな")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("été").unwrap());
+        assert!(re.is_match("ひらがな").unwrap());
+        assert!(!Regex::new("ÉTÉ").unwrap().is_match("été").unwrap());
+    }
+
+    /// 引数をパース
+    fn parse_args(args: &[&str]) -> Result<Options, regex_engine::helper::DynError> {
+        Options::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_cli_options() {
+        let opts =
+            parse_args(&["-inv", "-A2", "--context", "3", "--color", "ab", "x", "-"]).unwrap();
+        assert_eq!(opts.pattern, "ab");
+        assert_eq!(opts.files, ["x", "-"]);
+        assert!(opts.ignore_case && opts.line_number && opts.invert);
+        assert!(!opts.count && !opts.only_matching);
+        assert_eq!((opts.after, opts.before), (2, 3));
+        assert_eq!(opts.color, Color::Auto);
+
+        let opts = parse_args(&["-B", "1", "--", "-c"]).unwrap();
+        assert_eq!(opts.pattern, "-c");
+        assert_eq!((opts.after, opts.before), (0, 1));
+        assert!(!opts.count);
+
+        assert!(parse_args(&["-h"]).unwrap().help);
+        assert!(parse_args(&[]).is_err());
+        assert!(parse_args(&["-x", "a"]).is_err());
+        assert!(parse_args(&["-A", "a"]).is_err());
+        assert!(parse_args(&["a", "-A"]).is_err());
+        assert!(parse_args(&["--color=red", "a"]).is_err());
+        assert!(parse_args(&["--count=1", "a"]).is_err());
+
+        assert_eq!(parse_args(&["a"]).unwrap().debug, None);
+        assert_eq!(
+            parse_args(&["--debug", "a"]).unwrap().debug,
+            Some(DebugFormat::Text)
+        );
+        assert_eq!(
+            parse_args(&["--debug=dot", "a"]).unwrap().debug,
+            Some(DebugFormat::Dot)
+        );
+        assert!(parse_args(&["--debug=xml", "a"]).is_err());
+    }
+
+    /// textを探索し、出力を返す
+    fn search(args: &[&str], text: &str, color: bool) -> String {
+        let opts = parse_args(args).unwrap();
+        let regex = opts.build_regex().unwrap();
+        let mut searcher = Searcher::new(&regex, &opts, color, Vec::new());
+        searcher.search_reader("t.txt", text.as_bytes()).unwrap();
+        String::from_utf8(searcher.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_cli_search() {
+        let text = "one\nTwo apples\nthree\nfour\nfive apple\nsix\nseven\neight\nnine Apple\n";
+
+        assert_eq!(
+            search(&["-n", "apple"], text, false),
+            "2:Two apples\n5:five apple\n"
+        );
+        assert_eq!(search(&["-ic", "apple"], text, false), "3\n");
+        assert_eq!(search(&["-v", "[aeiu]"], text, false), "");
+        assert_eq!(search(&["-vn", "e"], text, false), "4:four\n6:six\n");
+        assert_eq!(search(&["-o", "p+"], text, false), "pp\npp\npp\n");
+        assert_eq!(search(&["-l", "six"], text, false), "t.txt\n");
+        assert_eq!(search(&["-l", "ten"], text, false), "");
+        assert_eq!(
+            search(&["-n", "ひらがな"], "カタカナ\nひらがなです\n", false),
+            "2:ひらがなです\n"
+        );
+
+        // 前後の行と、離れたまとまりの区切り
+        assert_eq!(
+            search(&["-in", "-C1", "apple"], text, false),
+            "1-one\n2:Two apples\n3-three\n4-four\n5:five apple\n6-six\n--\n8-eight\n9:nine Apple\n"
+        );
+        assert_eq!(
+            search(&["-B2", "^f"], text, false),
+            "Two apples\nthree\nfour\nfive apple\n"
+        );
+
+        // 色付け
+        assert_eq!(
+            search(&["-n", "p+"], "apple pie\n", true),
+            "\x1b[32m1\x1b[0m\x1b[36m:\x1b[0ma\x1b[01;31mpp\x1b[0mle \x1b[01;31mp\x1b[0mie\n"
+        );
+        // 空文字列へのマッチは色付けしない
+        assert_eq!(search(&["x*"], "ab\n", true), "ab\n");
+    }
+
+    #[test]
+    fn test_flags() {
+        // (正規表現, 文字列, マッチした部分文字列)
+        let cases = [
+            // 大文字と小文字を区別しない
+            ("(?i)tokyo", "東京はTOKYO", Some("TOKYO")),
+            ("(?i)ｒｕｓｔ", "言語はＲｕｓｔです", Some("Ｒｕｓｔ")),
+            ("(?i)σοφία", "ΣΟΦΊΑ", Some("ΣΟΦΊΑ")),
+            ("(?i)ς", "Σ", Some("Σ")),
+            ("(?i)k", "\u{212a}", Some("\u{212a}")),
+            ("(?i)[a-c]+", "xAbCx", Some("AbC")),
+            ("(?i)ı", "I", None),
+            ("(?i)ひらがな", "ヒラガナ", None),
+            // フラグの範囲
+            ("a(?i)b", "aB", Some("aB")),
+            ("a(?i)b", "AB", None),
+            ("(?i:a)b", "AB", None),
+            ("(?i:a)b", "Ab", Some("Ab")),
+            ("((?i)a)b", "AB", None),
+            ("(?i)a(?-i)b", "Ab", Some("Ab")),
+            ("(?i)a(?-i)b", "AB", None),
+            ("(?i)a|b", "B", Some("B")),
+            // 行頭と行末
+            ("^Tokyo$", "東京\nTokyo\n大阪", None),
+            ("(?m)^Tokyo$", "東京\nTokyo\n大阪", Some("Tokyo")),
+            ("(?m)^大阪$", "東京\nTokyo\n大阪", Some("大阪")),
+            ("(?m)京$", "東京\nTokyo\n大阪", Some("京")),
+            ("(?m)$\n^T", "東京\nTokyo", Some("\nT")),
+            ("(?m)^$", "東京\n\n大阪", Some("")),
+            ("(?m)^$", "東京", None),
+            // 改行にマッチする.
+            ("京.T", "東京\nTokyo", None),
+            ("(?s)京.T", "東京\nTokyo", Some("京\nT")),
+            ("(?s:.)+", "a\nb", Some("a\nb")),
+            ("(?ms)^T.*$", "東京\nTokyo\n大阪", Some("Tokyo\n大阪")),
+            ("(?m)^T.*$", "東京\nTokyo\n大阪", Some("Tokyo")),
+        ];
+
+        for (expr, text, expected) in cases {
+            for is_depth in [true, false] {
+                let re = RegexBuilder::new(expr)
+                    .depth_first(is_depth)
+                    .build()
+                    .unwrap();
+                let m = re.find(text).unwrap();
+                assert_eq!(m.map(|m| m.as_str()), expected, "{expr} {text:?}");
+
+                let dfa = RegexBuilder::new(expr).lazy_dfa(true).build().unwrap();
+                assert_eq!(
+                    dfa.is_match(text).unwrap(),
+                    expected.is_some(),
+                    "{expr} {text:?}"
+                );
+            }
+        }
+
+        // ビルダーでの指定
+        let re = RegexBuilder::new("^tokyo.")
+            .case_insensitive(true)
+            .multi_line(true)
+            .dot_matches_new_line(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            re.find("東京\nTOKYO\n").unwrap().unwrap().as_str(),
+            "TOKYO\n"
+        );
+        // ビルダーで指定したフラグも、正規表現中で無効にできる
+        let re = RegexBuilder::new("(?-i)a")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(!re.is_match("A").unwrap());
+
+        // 不正なフラグ
+        for expr in ["(?x)a", "(?i", "(?-)a", "(?i-:a)", "(?)a", "(?i-m-s)a"] {
+            assert!(Regex::new(expr).is_err(), "{expr}");
+        }
+    }
+
+    #[test]
+    fn test_replace() {
+        let re = Regex::new("(?P<y>[0-9]{4})年([0-9]+)月").unwrap();
+        let text = "2024年1月と2025年12月";
+
+        // テンプレートの展開
+        assert_eq!(re.replace(text, "$2/$y").unwrap(), "1/2024と2025年12月");
+        assert_eq!(
+            re.replace_all(text, "${y}-${2}").unwrap(),
+            "2024-1と2025-12"
+        );
+        assert_eq!(re.replace_all(text, "$2x").unwrap(), "と"); // $2xという名前のグループ
+        assert_eq!(re.replace_all(text, "${2}x").unwrap(), "1xと12x");
+        assert_eq!(
+            re.replace_all(text, "$$ $9 $ ${").unwrap(),
+            "$  $ ${と$  $ ${"
+        );
+        assert_eq!(
+            re.replacen(text, 1, String::from("[$0]")).unwrap(),
+            "[2024年1月]と2025年12月"
+        );
+        assert_eq!(re.replace_all(text, NoExpand("$y")).unwrap(), "$yと$y");
+
+        // クロージャによる置換
+        let mut n = 0;
+        let replaced = re
+            .replace_all(text, |caps: &Captures| {
+                n += 1;
+                format!(
+                    "{}.{}",
+                    caps.get(2).unwrap().as_str(),
+                    caps.name("y").unwrap().as_str().len()
+                )
+            })
+            .unwrap();
+        assert_eq!(replaced, "1.4と12.4");
+        assert_eq!(n, 2);
+
+        // マッチしない場合は元の文字列をそのまま返す
+        let replaced = re.replace_all("なし", "x").unwrap();
+        assert!(matches!(replaced, std::borrow::Cow::Borrowed("なし")));
+
+        // マッチに関与しなかったグループは空文字列
+        let re = Regex::new("(a)|(b)").unwrap();
+        assert_eq!(re.replace_all("ab", "[$1$2]").unwrap(), "[a][b]");
+
+        // 長さ0のマッチ
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(re.replace_all("abc", "-").unwrap(), "-a-b-c-");
+        assert_eq!(re.replace_all("axxb", "-").unwrap(), "-a-b-");
+    }
+
+    #[test]
+    fn test_split() {
+        let split = |expr: &str, text: &str| -> Vec<String> {
+            let re = Regex::new(expr).unwrap();
+            re.split(text).map(|s| s.unwrap().to_string()).collect()
+        };
+
+        assert_eq!(
+            split("[ 　]+", "東京 大阪　 名古屋"),
+            ["東京", "大阪", "名古屋"]
+        );
+        assert_eq!(split(",", ",a,,b,"), ["", "a", "", "b", ""]);
+        assert_eq!(split(",", "abc"), ["abc"]);
+        assert_eq!(split(",", ""), [""]);
+        assert_eq!(split("x*", "あいう"), ["", "あ", "い", "う", ""]);
+
+        // 評価時のエラーはErrとして返す
+        let re = RegexBuilder::new("(a|aa)*b")
+            .depth_first(true)
+            .step_limit(Some(1000))
+            .build()
+            .unwrap();
+        let text = "a".repeat(30);
+        let result: Vec<_> = re.split(&text).collect();
+        assert_eq!(result.len(), 1);
+        assert!(result[0].is_err());
+    }
+
+    #[test]
+    fn test_optimize() {
+        let exprs = [
+            "abc|(de|cd)+",
+            "a|b|c|[x-z]",
+            "(?:a|b)*c",
+            "(a|b)*?c",
+            "x(a|[^a-y]|c)+y",
+            "(?:ab|cd)*e",
+            "東京(都|府)",
+            "(?i)tokyo|osaka",
+            "ab(c|d)?$",
+            "^ab|b",
+            "(a|b){2,3}",
+            "a+?",
+            "(?m)^(a|b)$",
+        ];
+        let texts = [
+            "",
+            "abc",
+            "xxdecdde",
+            "xazcy",
+            "xaby",
+            "ababcde",
+            "東京都と京都府",
+            "TOKYO OSAKA",
+            "abcab",
+            "bab",
+            "aaab",
+            "c\nb\na",
+        ];
+
+        for expr in exprs {
+            for is_depth in [true, false] {
+                let optimized = RegexBuilder::new(expr)
+                    .depth_first(is_depth)
+                    .build()
+                    .unwrap();
+                let naive = RegexBuilder::new(expr)
+                    .depth_first(is_depth)
+                    .optimize(false)
+                    .build()
+                    .unwrap();
+                let dfa = RegexBuilder::new(expr).lazy_dfa(true).build().unwrap();
+
+                for text in texts {
+                    let expected = naive
+                        .captures_iter(text)
+                        .map(|caps| {
+                            let caps = caps.unwrap();
+                            (0..caps.len())
+                                .map(|i| caps.get(i).map(|m| m.range()))
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>();
+                    let actual = optimized
+                        .captures_iter(text)
+                        .map(|caps| {
+                            let caps = caps.unwrap();
+                            (0..caps.len())
+                                .map(|i| caps.get(i).map(|m| m.range()))
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>();
+                    assert_eq!(actual, expected, "{expr} {text:?}");
+                    assert_eq!(
+                        optimized.is_match(text).unwrap(),
+                        naive.is_match(text).unwrap(),
+                        "{expr} {text:?}"
+                    );
+                    assert_eq!(
+                        dfa.is_match(text).unwrap(),
+                        naive.is_match(text).unwrap(),
+                        "{expr} {text:?}"
+                    );
+                }
+            }
+        }
+
+        // プレフィックスより後ろからの探索
+        let re = Regex::new("ab+c").unwrap();
+        let found = re
+            .find_iter("xabcxxabbbcab")
+            .map(|m| m.unwrap().range())
+            .collect::<Vec<_>>();
+        assert_eq!(found, [1..4, 6..11]);
+        assert!(!re.is_match("xxabxbc").unwrap());
+    }
+
+    #[test]
+    fn test_export() {
+        let re = RegexBuilder::new("(?P<n>a|\n)+?\"$")
+            .optimize(false)
+            .build()
+            .unwrap();
+
+        let json = export_json(&re);
+        assert!(json.starts_with(r#"{"expr":"(?P<n>a|\n)+?\"$","prefix":null,"ast":"#));
+        assert!(json.contains(
+            r#"{"type":"plus","greedy":false,"expr":{"type":"capture","index":1,"name":"n","expr":{"type":"or","left":"#
+        ));
+        assert!(json.contains(r#"{"type":"char","char":"\n"}"#));
+        assert!(json.contains(r#"{"type":"assert","assertion":"end"}"#));
+        assert!(json.contains(r#"{"pc":3,"op":"save","slot":0}"#));
+        assert!(json.ends_with(r#"{"pc":14,"op":"match","id":0}]}"#));
+
+        let dot = export_dot(&re);
+        assert!(dot.starts_with("digraph regex {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains(r#"ast0 [label="seq"];"#));
+        assert!(dot.contains(r#"ast1 [label="+?"];"#));
+        assert!(dot.contains(r#"ast2 [label="group 1 <n>"];"#));
+        assert!(dot.contains(r#"[label="'\\n'"];"#));
+        assert!(dot.contains(r#"[label="'\"'"];"#));
+        // splitは優先順位をラベルとする2本の辺、jumpは1本の辺となる
+        assert!(dot.contains("pc0 -> pc3 [label=\"1\"];\n        pc0 -> pc1 [label=\"2\"];"));
+        assert!(dot.contains("pc2 -> pc0;"));
+        assert!(dot.contains(r#"pc14 [label="0014: match 0", peripheries=2];"#));
+        assert!(!dot.contains("pc14 ->"));
+    }
+
+    /// exprのパースエラーを返す
+    fn parse_error(expr: &str) -> ParseError {
+        let err = Regex::new(expr).unwrap_err();
+        match err.downcast::<ParseError>() {
+            Ok(err) => *err,
+            Err(err) => panic!("not a parse error: {err}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let spans = [
+            ("a\\qb", 1, 3),
+            ("ab)c", 2, 3),
+            ("a|*", 2, 3),
+            ("a||b", 2, 3),
+            ("a{2}x{3", 5, 7),
+            ("ab(c(d)", 2, 3),
+            ("", 0, 0),
+            ("(?:)", 0, 4),
+            ("x[abc", 1, 5),
+            ("[a-\\d]", 1, 5),
+            ("[z-a]", 1, 4),
+            ("\\u{110000}", 0, 10),
+            ("(?x)", 0, 3),
+            ("(?P<1a>x)", 0, 5),
+            ("(?P<a>x)(?<a>y)", 8, 13),
+            ("a{2,1}", 1, 6),
+            ("ab\\", 2, 3),
+        ];
+        for (expr, start, end) in spans {
+            assert_eq!(parse_error(expr).span(), Span::new(start, end), "{expr}");
+        }
+
+        // 限量子の連続
+        for expr in [
+            "a**", "a+*", "a*+", "a???", "a+??", "a{2}{3}", "(a)*{2}", "a*?+",
+        ] {
+            assert!(
+                matches!(parse_error(expr), ParseError::NestedQuantifier(_)),
+                "{expr}"
+            );
+        }
+        assert_eq!(parse_error("ab+*c").span(), Span::new(3, 4));
+        assert_eq!(parse_error("a+{2,3}").span(), Span::new(2, 7));
+
+        // 修飾する式がない限量子
+        for expr in ["*a", "a|+", "(?i)*", "a(?i)*", "a(?:)+", "(*a)"] {
+            assert!(matches!(parse_error(expr), ParseError::NoPrev(_)), "{expr}");
+        }
+
+        // 非貪欲な限量子や、エスケープした限量子は連続してもよい
+        for expr in ["a+?", "a*?b??", "a{2}?", "a*\\*", "(a*)*", "(?:a+)+"] {
+            assert!(Regex::new(expr).is_ok(), "{expr}");
+        }
+
+        assert_eq!(
+            parse_error("a(b**").render("a(b**"),
+            "ParseError: nested quantifier: pos = 4..5\n  a(b**\n      ^"
+        );
+        // 全角文字は2桁分、制御文字はエスケープして表示
+        assert_eq!(
+            parse_error("東京\t[").render("東京\t["),
+            "ParseError: unclosed character class: pos = 3..4\n  東京\\t[\n        ^"
+        );
+        assert_eq!(
+            parse_error("a[b-").render("a[b-"),
+            "ParseError: unclosed character class: pos = 1..4\n  a[b-\n   ^^^"
+        );
+        assert_eq!(
+            parse_error("").render(""),
+            "ParseError: empty expression: pos = 0..0\n  \n  ^"
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        // 正しいUTF-8の場合は、文字単位の探索とバイト位置が一致する
+        let exprs = [
+            "[あ-ん]+",
+            "x*",
+            "(?m)^.$",
+            "東京|京都",
+            "[^a]",
+            "(?i)straße",
+        ];
+        let texts = [
+            "ひらがなとカタカナ",
+            "あxいxx",
+            "a\nあ\nbc",
+            "東京都と京都府",
+            "STRASSE Straße",
+        ];
+        for expr in exprs {
+            for is_depth in [true, false] {
+                let re = RegexBuilder::new(expr)
+                    .depth_first(is_depth)
+                    .lazy_dfa(true)
+                    .build()
+                    .unwrap();
+                for text in texts {
+                    let expected = re
+                        .find_iter(text)
+                        .map(|m| m.unwrap().range())
+                        .collect::<Vec<_>>();
+                    let actual = re
+                        .find_iter_bytes(text.as_bytes())
+                        .map(|m| m.unwrap().range())
+                        .collect::<Vec<_>>();
+                    assert_eq!(actual, expected, "{expr} {text}");
+                    assert_eq!(
+                        re.is_match_bytes(text.as_bytes()).unwrap(),
+                        !expected.is_empty()
+                    );
+                }
+            }
+        }
+
+        // 不正なバイトは、1バイトずつU+FFFDとみなす
+        for is_depth in [true, false] {
+            let re = |expr: &str| {
+                RegexBuilder::new(expr)
+                    .depth_first(is_depth)
+                    .build()
+                    .unwrap()
+            };
+            let bytes = b"a\xffb \xe3\x81c \xe3\x81\x82";
+            assert!(re("a.b").is_match_bytes(bytes).unwrap());
+            assert!(re("\\u{fffd}\\u{fffd}c").is_match_bytes(bytes).unwrap());
+            assert!(!re("a\\u{fffd}\\u{fffd}b").is_match_bytes(bytes).unwrap());
+            assert!(!re("\\u{fffd}{3}").is_match_bytes(bytes).unwrap());
+
+            let m = re("[^ab ]+").find_bytes(bytes).unwrap().unwrap();
+            assert_eq!(m.range(), 1..2);
+            assert_eq!(m.as_bytes(), b"\xff");
+            let found = re("[^ ]+$")
+                .find_iter_bytes(bytes)
+                .map(|m| m.unwrap().as_bytes())
+                .collect::<Vec<_>>();
+            assert_eq!(found, [b"\xe3\x81\x82"]);
+
+            // 長さ0のマッチは文字の途中に現れない
+            let found = re("x*")
+                .find_iter_bytes(b"\xe3\x81\x82\xff")
+                .map(|m| m.unwrap().start())
+                .collect::<Vec<_>>();
+            assert_eq!(found, [0, 3, 4]);
+        }
+
+        let re = RegexBuilder::new("c.$").lazy_dfa(true).build().unwrap();
+        assert!(re.is_match_bytes(b"\xe3c\xe3").unwrap());
+        assert!(!re.is_match_bytes(b"\xe3c\xe3\x81\x82\xe3").unwrap());
+
+        // 不正なバイトを含む行も、そのまま出力する
+        let opts = parse_args(&["-n", "-o", "[^ ]+r"]).unwrap();
+        let regex = opts.build_regex().unwrap();
+        let mut searcher = Searcher::new(&regex, &opts, false, Vec::new());
+        let count = searcher
+            .search_reader("t.txt", &b"ok\n\xff\xfe error\n\xc0bar\n"[..])
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(searcher.into_inner(), b"2:error\n3:\xc0bar\n");
+    }
+
+    #[test]
+    fn test_backref() {
+        let re = |expr: &str| RegexBuilder::new(expr).depth_first(true).build().unwrap();
+        let find =
+            |expr: &str, text: &str| re(expr).find(text).unwrap().map(|m| m.as_str().to_string());
+
+        assert_eq!(find("(\\w+) \\1", "it is is ok"), Some("is is".into()));
+        assert_eq!(
+            find("(?P<w>[0-9]+)-\\k<w>", "12-13 13-13"),
+            Some("13-13".into())
+        );
+        assert_eq!(
+            find("<(?<tag>\\w+)>.*?</\\k<tag>>", "<a><b>x</b></a>"),
+            Some("<a><b>x</b></a>".into())
+        );
+        assert_eq!(find("(a*)b\\1$", "aaabaa"), Some("aabaa".into()));
+        assert_eq!(find("(あ|い)\\1", "あいい"), Some("いい".into()));
+        // \10は\1の後に0が続くもの
+        assert_eq!(find("(a)\\10", "aa0"), Some("aa0".into()));
+
+        // マッチに関与していないグループへの後方参照は失敗する
+        assert_eq!(find("(?:(a)|b)\\1", "b"), None);
+        assert_eq!(find("(?:(a)|b)\\1", "baa"), Some("aa".into()));
+        assert_eq!(find("(a\\1)", "aa"), None);
+
+        // 大文字と小文字を区別しない場合は、後方参照も区別しない
+        assert_eq!(
+            find("(?i)(k+)-\\1", "kK-K\u{212a}k"),
+            Some("kK-K\u{212a}".into())
+        );
+        assert_eq!(find("(k+)-(?i:\\1)", "kK-Kk"), None);
+        assert_eq!(find("(k+)-(?i:\\1)", "kk-Kk"), Some("kk-Kk".into()));
+
+        // キャプチャグループの範囲
+        let caps = re("(a|b)(\\1)").captures("xbb").unwrap().unwrap();
+        assert_eq!(caps.get(2).unwrap().range(), 2..3);
+        assert!(re("(a)(b)\\2").is_match("abb").unwrap());
+        assert!(do_matching("(a)\\1", "aa", true).unwrap());
+
+        // メモ化を指定しても、後方参照がある場合はメモ化しない
+        let memo = RegexBuilder::new("^(ab|a)b?\\1$")
+            .depth_first(true)
+            .memoize(true)
+            .lazy_dfa(true)
+            .build()
+            .unwrap();
+        assert!(memo.is_match("aba").unwrap());
+        assert!(memo.is_match_bytes(b"abab").unwrap());
+        assert!(!memo.is_match("abb").unwrap());
+
+        // 参照先のグループがない場合はパースエラー
+        for expr in [
+            "\\1(a)",
+            "(a)\\2",
+            "\\k<x>(?P<x>a)",
+            "(?P<x>a)\\k<y>",
+            "(a)\\k",
+            "(a)\\k<1>",
+        ] {
+            assert!(
+                RegexBuilder::new(expr).depth_first(true).build().is_err(),
+                "{expr}"
+            );
+        }
+        assert!(Regex::new("(a)[\\1]").is_err());
+
+        // 幅優先探索では評価できない
+        assert!(RegexBuilder::new("(a)\\1").build().is_err());
+        assert!(RegexBuilder::new("(a)\\1").lazy_dfa(true).build().is_err());
+        assert!(do_matching("(a)\\1", "aa", false).is_err());
+
+        // CLIは、後方参照を含む場合のみ深さ優先探索でコンパイルする
+        let opts = parse_args(&["-o", "(\\w+) \\1"]).unwrap();
+        let regex = opts.build_regex().unwrap();
+        let mut searcher = Searcher::new(&regex, &opts, false, Vec::new());
+        searcher
+            .search_reader("t.txt", &b"it is is ok\nno no\n"[..])
+            .unwrap();
+        assert_eq!(searcher.into_inner(), b"is is\nno no\n");
+        let err = parse_args(&["(a"]).unwrap().build_regex().unwrap_err();
+        assert!(err.downcast_ref::<ParseError>().is_some());
+        assert!(do_searching("(a)\\1", "aa", false).is_err());
+    }
+
+    #[test]
+    fn test_lookaround() {
+        // 深さ優先探索と幅優先探索で、同じマッチ範囲になることを確認
+        let find = |expr: &str, text: &str| {
+            let mut ranges = Vec::new();
+            for depth in [false, true] {
+                let re = RegexBuilder::new(expr).depth_first(depth).build().unwrap();
+                ranges.push(re.find(text).unwrap().map(|m| m.range()));
+            }
+            assert_eq!(ranges[0], ranges[1], "{expr}");
+            ranges[0].clone()
+        };
+
+        // 先読み
+        assert_eq!(find("foo(?=bar)", "foobaz foobar"), Some(7..10));
+        assert_eq!(find("foo(?!bar)", "foobar foobaz"), Some(7..10));
+        assert_eq!(find("(?=\\d{3}$)\\d", "12345"), Some(2..3));
+        assert_eq!(find("a(?=)", "a"), Some(0..1));
+        assert_eq!(find("a(?!)", "a"), None);
+
+        // 後読み
+        assert_eq!(find("(?<=\\$)\\d+", "a1 $23"), Some(4..6));
+        assert_eq!(find("(?<!\\$)\\b\\d+", "$23 45"), Some(4..6));
+        assert_eq!(find("(?<=a|bc)d", "bcd"), Some(2..3));
+        assert_eq!(find("(?<=^|,)x", "ax,x"), Some(3..4));
+        assert_eq!(find("(?<=a{2,3})b", "ab aab"), Some(5..6));
+        assert_eq!(find("(?<=ab)", "xab"), Some(3..3));
+        // 後読みより前の位置の文字も参照する
+        assert_eq!(find("b(?<=ab)c", "bc abc"), Some(4..6));
+        // 多バイト文字の位置もバイト単位で求める
+        assert_eq!(find("(?<=あい)う", "うあいう"), Some(9..12));
+        assert_eq!(find("(?<!あ)い", "あいうい"), Some(9..12));
+
+        // 単語の境界
+        assert_eq!(find("\\bis\\b", "this is"), Some(5..7));
+        assert_eq!(find("\\Bis", "is this"), Some(5..7));
+        assert_eq!(find("\\b", ""), None);
+        assert_eq!(find("\\B", ""), Some(0..0));
+        assert_eq!(find("a\\b", "aあ"), Some(0..1));
+
+        // 肯定の先読みと後読みのキャプチャグループも記録する
+        for depth in [false, true] {
+            let re = RegexBuilder::new("(?=(\\w+))\\w(?<=(.))")
+                .depth_first(depth)
+                .build()
+                .unwrap();
+            let caps = re.captures("ab").unwrap().unwrap();
+            assert_eq!(caps.get(1).unwrap().as_str(), "ab");
+            assert_eq!(caps.get(2).unwrap().as_str(), "a");
+        }
+        let re = RegexBuilder::new("(?=(a+))a*b\\1")
+            .depth_first(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.find("baaabac").unwrap().unwrap().as_str(), "aba");
+
+        // is_matchとバイト列での評価。遅延DFAで評価できない場合も同じ結果となる
+        let re = RegexBuilder::new("a(?<=ba)\\b")
+            .lazy_dfa(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("xba").unwrap());
+        assert!(!re.is_match("xa ba_").unwrap());
+        assert!(re.is_match_bytes(b"\xffba").unwrap());
+        let re = Regex::new("(?<!\\w)\\d").unwrap();
+        let ranges: Vec<_> = re
+            .find_iter_bytes(b"1 a2 \xff3")
+            .map(|m| m.unwrap().start())
+            .collect();
+        assert_eq!(ranges, [0, 6]);
+        assert!(do_matching("a(?=b)", "ab", false).unwrap());
+        assert!(!do_matching("a(?!b)", "ab", true).unwrap());
+
+        // [\b]は誤ったエスケープ
+        assert!(Regex::new("[\\b]").is_err());
+
+        // 後読みの式にマッチする文字数に上限がない場合はエラー
+        for (expr, start, end) in [
+            ("(?<=a+)b", 0, 7),
+            ("x(?<!a|b*)", 1, 10),
+            ("(?<=(a)\\1)", 0, 10),
+            ("(?<=a{2,})", 0, 10),
+        ] {
+            let err = Regex::new(expr).unwrap_err();
+            match err.downcast_ref::<ParseError>() {
+                Some(ParseError::UnboundedLookBehind(span)) => {
+                    assert_eq!(*span, Span::new(start, end), "{expr}")
+                }
+                _ => panic!("{expr}: {err}"),
+            }
+        }
+        assert!(Regex::new("(?<=a{2}(?=b)|^)c").is_ok());
+        assert!(Regex::new("(?<=()*)c").is_ok());
+
+        // 書き出し
+        let re = Regex::new("(?<!ab)c\\b").unwrap();
+        let json = export_json(&re);
+        assert!(json.contains(
+            r#"{"type":"look","direction":"behind","negative":true,"min":2,"max":2,"expr":"#
+        ));
+        assert!(json.contains(
+            r#""op":"look","direction":"behind","negative":true,"min":2,"max":2,"next":8}"#
+        ));
+        assert!(json.contains(r#""assertion":"word_boundary""#));
+        assert!(export_dot(&re).contains("pc4 -> pc5 [style=dashed];"));
+    }
+
+    #[test]
+    fn test_regex_set() {
+        let exprs = [
+            "error",
+            "warn(ing)?",
+            "^[0-9]+$",
+            "(?i)disk",
+            "(?<=user=)root\\b",
+            "a|b*",
+            "\\bfail(?!ed)",
+            "あ+い",
+        ];
+        let set = RegexSet::new(exprs).unwrap();
+        assert_eq!(set.len(), 8);
+        assert_eq!(set.patterns()[1], "warn(ing)?");
+
+        // 各正規表現を個別に評価した結果と一致する
+        for text in [
+            "warning: DISK error",
+            "12345",
+            "user=root login",
+            "user=rooted",
+            "failed to fail",
+            "ああい",
+            "",
+            "xyz",
+        ] {
+            let matches = set.matches(text).unwrap();
+            for (i, expr) in exprs.iter().enumerate() {
+                let expected = Regex::new(expr).unwrap().is_match(text).unwrap();
+                assert_eq!(matches.matched(i), expected, "{expr} {text:?}");
+            }
+            assert_eq!(set.is_match(text).unwrap(), matches.matched_any());
+            assert_eq!(set.matches_bytes(text.as_bytes()).unwrap(), matches);
+        }
+
+        let matches = set.matches("warning: DISK error").unwrap();
+        assert_eq!(matches.iter().collect::<Vec<_>>(), [0, 1, 3, 5]);
+        assert_eq!(matches.len(), 8);
+        assert!(!matches.matched(100));
+
+        let set = RegexSet::new(["[0-9]+", "z$"]).unwrap();
+        assert!(set.matches_bytes(b"\xff1").unwrap().matched(0));
+        assert!(!set.is_match("abc").unwrap());
+        assert!(!set.matches("abc").unwrap().matched_any());
+
+        // 正規表現がない場合は、何にもマッチしない
+        let set = RegexSet::new(Vec::<String>::new()).unwrap();
+        assert!(set.is_empty());
+        assert!(!set.is_match("abc").unwrap());
+        assert!(set.matches("abc").unwrap().is_empty());
+
+        // エラーの場合は、正規表現の番号を返す
+        for (exprs, index) in [(vec!["a", "b(", "c"], 1), (vec!["a", "(b)\\1"], 1)] {
+            let err = RegexSet::new(exprs).unwrap_err();
+            match err.downcast_ref::<SetError>() {
+                Some(SetError::InvalidPattern(i, _)) => assert_eq!(*i, index),
+                _ => panic!("{err}"),
+            }
+        }
+        let err = RegexSet::new(["a", "b)"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("SetError: invalid pattern: index = 1, ParseError"));
+    }
+
+    #[test]
+    fn test_stream() {
+        use std::io::{self, Read};
+
+        // 1度にnバイトずつ返すリーダ
+        struct Chunked<'a>(&'a [u8], usize);
+        impl Read for Chunked<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = self.1.min(buf.len()).min(self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let ranges = |re: &Regex, bytes: &[u8], n: usize| {
+            re.stream_matches(Chunked(bytes, n))
+                .unwrap()
+                .map(|m| m.unwrap().range())
+                .collect::<Vec<_>>()
+        };
+
+        // チャンクの境界をまたぐマッチ。マルチバイト文字の途中で区切られる場合も含む
+        let re = Regex::new("あい*う").unwrap();
+        let text = "xあいいうyあう".as_bytes();
+        for n in 1..=4 {
+            assert_eq!(ranges(&re, text, n), [1..13, 14..20]);
+        }
+
+        // find_iter_bytesと同じ位置にマッチする
+        let mut long = format!("{}abc{}\n", "x".repeat(10000), "y".repeat(10000)).into_bytes();
+        long.extend(b"\xffend");
+        for (expr, text) in [
+            ("[0-9]+", &b"a12b345\xff6"[..]),
+            ("a*", b"baaab"),
+            ("\\b", b"ab cd"),
+            ("^[a-z]+$", b"abc\ndef\n12\nghi"),
+            ("(?m)^[a-z]+$", b"abc\ndef\n12\nghi"),
+            ("x*abcy*", &long),
+            ("y+$|end$", &long),
+            ("い?", "あい".as_bytes()),
+        ] {
+            let re = Regex::new(expr).unwrap();
+            let expected = re
+                .find_iter_bytes(text)
+                .map(|m| m.unwrap().range())
+                .collect::<Vec<_>>();
+            for n in [1, 3, 8192] {
+                assert_eq!(ranges(&re, text, n), expected, "{expr}");
+            }
+        }
+
+        // 後方参照と先読み・後読みはErr
+        let re = RegexBuilder::new("(a)\\1")
+            .depth_first(true)
+            .build()
+            .unwrap();
+        assert!(re.stream_matches(&b""[..]).is_err());
+        for expr in ["a(?=b)", "(?<=a)b"] {
+            let re = Regex::new(expr).unwrap();
+            assert!(re.stream_matches(&b""[..]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_unicode_property() {
+        // マッチの開始位置とマッチした部分を、"位置:部分"の形で返す
+        let find_iter = |expr: &str, text: &str| {
+            Regex::new(expr)
+                .unwrap()
+                .find_iter(text)
+                .map(|m| {
+                    let m = m.unwrap();
+                    format!("{}:{}", m.start(), m.as_str())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // 位置はバイト単位となる。長音符ーは用字がCommonで、一般カテゴリがLm
+        let text = "東京タワーは333mで、とても高い";
+        assert_eq!(
+            find_iter("\\p{Hiragana}+", text),
+            ["15:は", "22:で", "28:とても", "40:い"]
+        );
+        assert_eq!(find_iter("\\p{Katakana}+", text), ["6:タワ"]);
+        assert_eq!(find_iter("\\p{Han}+", text), ["0:東京", "37:高"]);
+        assert_eq!(find_iter("\\p{N}+", text), ["18:333"]);
+        assert_eq!(
+            find_iter("\\pL+", text),
+            ["0:東京タワーは", "21:mで", "28:とても高い"]
+        );
+        assert_eq!(find_iter("\\p{N}", "٣Ⅻ½"), ["0:٣", "2:Ⅻ", "5:½"]);
+
+        // 否定と、文字クラス中での利用
+        assert_eq!(find_iter("\\P{L}+", text), ["18:333", "25:、"]);
+        assert_eq!(find_iter("[\\p{Katakana}ー]+", text), ["6:タワー"]);
+        assert_eq!(
+            find_iter("[^\\p{Han}\\p{Hiragana}]+", text),
+            ["6:タワー", "18:333m", "25:、"]
+        );
+
+        // 色付けする範囲も、マルチバイト文字の境界に揃う
+        assert_eq!(
+            search(&["\\p{Han}+"], "漢字とカナと字\nかな\n", true),
+            "\x1b[01;31m漢字\x1b[0mとカナと\x1b[01;31m字\x1b[0m\n"
+        );
+
+        // 未定義のプロパティ名と、書式の誤り
+        for (expr, start, end) in [
+            ("a\\p{Hira}", 1, 9),
+            ("\\p{Han", 0, 6),
+            ("\\p{-}", 0, 4),
+            ("\\pX", 0, 3),
+            ("a\\p", 1, 3),
+        ] {
+            let err = Regex::new(expr).unwrap_err();
+            match err.downcast_ref::<ParseError>() {
+                Some(ParseError::InvalidProperty(span)) => {
+                    assert_eq!(*span, Span::new(start, end), "{expr}")
+                }
+                _ => panic!("{expr}: {err}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_equivalence() {
+        let re = |expr: &str| Regex::new(expr).unwrap();
+        let equivalent = |a: &str, b: &str| re(a).equivalent(&re(b)).unwrap();
+        let includes = |a: &str, b: &str| re(a).includes(&re(b)).unwrap();
+
+        // 等価な正規表現
+        for (a, b) in [
+            ("(a|b)*", "(a*b*)*"),
+            ("a+", "aa*"),
+            ("[a-c]", "a|b|c"),
+            ("x{2,3}", "xxx?"),
+            ("(?i)ab", "[aA][bB]"),
+            ("^abc$", "abc"),
+            (".", "[^\\n]"),
+            ("(?s).", "[\\s\\S]"),
+            ("(?m)a$\\nb", "a\\nb"),
+            ("\\p{N}", "\\p{N}|[0-9]"),
+            ("(a|ab)(c|bcd)", "abcd|ac|abc|abbcd"),
+        ] {
+            assert_eq!(equivalent(a, b), Comparison::Holds, "{a} {b}");
+            assert_eq!(equivalent(b, a), Comparison::Holds, "{b} {a}");
+        }
+
+        // 等価でない場合は、最も短い反例を返す
+        for (a, b, text) in [
+            ("a*", "a+", ""),
+            ("(a|b)*", "a*b*", "ba"),
+            ("a{2,}", "a{2,4}", "aaaaa"),
+            ("a$\\nb", "a\\nb", "a\nb"),
+            ("[^a]", "[^ab]", "b"),
+            ("\\p{Hiragana}", "[ぁ-ゖ]", "ゝ"),
+        ] {
+            assert_eq!(
+                equivalent(a, b),
+                Comparison::Counterexample(text.to_string()),
+                "{a} {b}"
+            );
+        }
+
+        // 包含関係。反例は右辺のみにマッチする
+        assert!(includes("[a-z]+", "abc|xyz").holds());
+        assert!(includes("\\p{Hiragana}", "[ぁ-ゖ]").holds());
+        assert!(includes("(a|b)*", "a*b*").holds());
+        assert_eq!(includes("a*b*", "(a|b)*").counterexample(), Some("ba"));
+        assert_eq!(includes("[a-z]+", "[a-z]*").counterexample(), Some(""));
+        assert_eq!(includes("[0-9]+", "\\d+|x").counterexample(), Some("x"));
+
+        // DFAで表せない命令を含む場合はErr
+        for expr in ["\\bx", "a(?=b)"] {
+            let err = re(expr).equivalent(&re("x")).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<EquivError>(),
+                Some(EquivError::Unsupported)
+            ));
+        }
+        let backref = RegexBuilder::new("(a)\\1")
+            .depth_first(true)
+            .build()
+            .unwrap();
+        assert!(re("aa").includes(&backref).is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        let re = |expr: &str| RegexBuilder::new(expr).depth_first(true).build().unwrap();
+        // 全体にマッチするかを、^と$で囲んだ正規表現で判定
+        let is_full_match =
+            |expr: &str, text: &str| re(&format!("^(?:{expr})$")).is_match(text).unwrap();
+
+        for expr in [
+            "[a-z]{3}-[0-9]+",
+            "(foo|bar)+baz?",
+            "\\p{Hiragana}{2,4}",
+            "(\\w+) \\1",
+            "(?m)a$\\n^b",
+            "x(?=y)\\w",
+            "\\bword\\b",
+            "(?i)abc",
+            ".*",
+        ] {
+            let regex = re(expr);
+            let mut generator = regex.generator(1).unwrap();
+            for _ in 0..20 {
+                let text = generator.generate().unwrap();
+                assert!(is_full_match(expr, &text), "{expr}: {text:?}");
+                let text = generator.near_miss().unwrap();
+                assert!(!is_full_match(expr, &text), "{expr}: {text:?}");
+            }
+        }
+
+        // 同じシードからは同じ文字列を生成する
+        let regex = re("[a-z]+[0-9]*");
+        let generate = |seed: u64| {
+            let mut generator = regex.generator(seed).unwrap();
+            (0..10)
+                .map(|_| generator.generate().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(generate(7), generate(7));
+        assert_ne!(generate(7), generate(8));
+
+        // 空文字列にマッチする式の繰り返しも、全体にマッチするかを判定できる
+        for expr in ["(a*)*b", "(x?)*y", "((a*)*)*z"] {
+            let regex = Regex::new(expr).unwrap();
+            let mut generator = regex.generator(1).unwrap();
+            for _ in 0..10 {
+                let text = generator.generate().unwrap();
+                assert!(is_full_match(expr, &text), "{expr}: {text:?}");
+                let text = generator.near_miss().unwrap();
+                assert!(!is_full_match(expr, &text), "{expr}: {text:?}");
+            }
+        }
+
+        // 繰り返しの回数の上限
+        let regex = re("a*b+c{2,}");
+        let mut generator = regex.generator(0).unwrap();
+        generator.max_repeat(0);
+        assert_eq!(generator.generate().unwrap(), "bcc");
+        generator.max_repeat(2);
+        for _ in 0..20 {
+            let text = generator.generate().unwrap();
+            assert!(text.len() <= 2 + 3 + 4, "{text}");
+        }
+
+        // 条件を満たす文字列がない場合はErr
+        for (expr, near_miss) in [("[^\\s\\S]", false), ("a^b", false), ("(?s).*", true)] {
+            let regex = re(expr);
+            let mut generator = regex.generator(0).unwrap();
+            let err = if near_miss {
+                generator.near_miss().unwrap_err()
+            } else {
+                generator.generate().unwrap_err()
+            };
+            assert!(
+                matches!(
+                    err.downcast_ref::<GenerateError>(),
+                    Some(GenerateError::Exhausted(_))
+                ),
+                "{expr}"
+            );
+        }
+
+        // コマンドラインからの生成
+        let opts = parse_args(&["--generate", "5", "--seed=3", "--near-miss", "[ab]{2}"]).unwrap();
+        assert_eq!(
+            (opts.generate, opts.seed, opts.near_miss),
+            (Some(5), Some(3), true)
+        );
+        assert!(parse_args(&["--generate=x", "a"]).is_err());
+        let generate = |opts: &Options| {
+            let mut out = Vec::new();
+            let regex = opts.build_regex().unwrap();
+            cli::generate(&regex, 5, opts.near_miss, 3, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let output = generate(&opts);
+        assert_eq!(output.lines().count(), 5);
+        assert!(output.lines().all(|line| !is_full_match("[ab]{2}", line)));
+        assert_eq!(output, generate(&opts));
+
+        // 改行を含む文字列しか生成できない場合はErr
+        let regex = re("a\\nb");
+        assert!(cli::generate(&regex, 1, false, 0, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_differential() {
+        // 深さ優先探索、幅優先探索、遅延DFAの結果が一致する
+        for seed in 0..8 {
+            if let Err(e) = check_evaluators_random(seed, 100) {
+                panic!("seed = {seed}: {e}");
+            }
+        }
+
+        // 指数時間のバックトラックで命令数の上限を超える場合は、一致しないものとしない
+        if let Err(e) = check_evaluators_random(221, 200) {
+            panic!("seed = 221: {e}");
+        }
+        check_evaluators("(?:(?:(?s:.)?){0,4}){0,}a", "a\n  \n").unwrap();
+
+        // 優先順位、非貪欲な繰り返し、キャプチャの位置、アサーション
+        let cases = [
+            ("(a|ab)(c|bcd)(d*)", "abcd"),
+            ("(a*)+?b", "aab"),
+            ("((a)|b)+", "ab"),
+            ("(a?)*?$", "aa"),
+            ("(?m:^)(a|)\\b", " a\na"),
+            ("(?<=(あ))(a{2,}?)", "あaaa"),
+            ("(?=(a+))a*?(b)?", "aab"),
+            ("[^a]*(?!b)", "bb\n"),
+            ("(x?)*y", "y"),
+            ("(a*)*b", "b"),
+            ("((a*)*)*z", "aaz"),
+        ];
+        for (expr, input) in cases {
+            if let Err(e) = check_evaluators(expr, input) {
+                panic!("{e}");
+            }
+        }
+
+        // パースできない場合は、比較せずにErr
+        let err = check_evaluators("(a", "a").unwrap_err();
+        assert!(err.downcast_ref::<ParseError>().is_some());
+        assert!(err.downcast_ref::<DifferentialError>().is_none());
     }
 }